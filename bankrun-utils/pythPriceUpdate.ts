@@ -0,0 +1,68 @@
+import { BN } from "@coral-xyz/anchor";
+import { PublicKey } from "@solana/web3.js";
+import { ProgramTestContext } from "solana-bankrun";
+import { createHash } from "crypto";
+
+export const PYTH_RECEIVER_PROGRAM_ID = new PublicKey(
+  "rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ"
+);
+
+const PRICE_UPDATE_V2_DISCRIMINATOR = createHash("sha256")
+  .update("account:PriceUpdateV2")
+  .digest()
+  .subarray(0, 8);
+
+// discriminator + write authority + verification level + price message + posted slot
+const PRICE_UPDATE_V2_LEN = 8 + 32 + 2 + 84 + 8;
+
+export type PriceUpdate = {
+  price: number | BN;
+  exponent?: number;
+  confidence?: number | BN;
+  // Defaults to the current bankrun clock
+  publishTime?: number;
+};
+
+const i64 = (value: number | BN) =>
+  new BN(value).toTwos(64).toArrayLike(Buffer, "le", 8);
+
+// Writes a fully verified Pyth `PriceUpdateV2` account at `address`, so tests can run at any
+// price and timestamp without fetching feeds from a live cluster.
+export const setPythPriceUpdate = async (
+  context: ProgramTestContext,
+  address: PublicKey,
+  feedId: string,
+  { price, exponent = -8, confidence = 0, publishTime }: PriceUpdate
+) => {
+  if (publishTime === undefined) {
+    const clock = await context.banksClient.getClock();
+    publishTime = Number(clock.unixTimestamp);
+  }
+
+  const exponentBuffer = Buffer.alloc(4);
+  exponentBuffer.writeInt32LE(exponent);
+
+  const data = Buffer.alloc(PRICE_UPDATE_V2_LEN);
+  Buffer.concat([
+    PRICE_UPDATE_V2_DISCRIMINATOR,
+    PublicKey.default.toBuffer(),
+    // VerificationLevel::Full
+    Buffer.from([1]),
+    Buffer.from(feedId.replace(/^0x/, ""), "hex"),
+    i64(price),
+    i64(confidence),
+    exponentBuffer,
+    i64(publishTime),
+    i64(publishTime),
+    i64(price),
+    i64(confidence),
+    i64(0),
+  ]).copy(data);
+
+  context.setAccount(address, {
+    lamports: 1_000_000_000,
+    data,
+    owner: PYTH_RECEIVER_PROGRAM_ID,
+    executable: false,
+  });
+};
@@ -1,10 +1,13 @@
 use anchor_lang::prelude::*;
 
 #[constant]
-
-pub const SOL_USD_FEED_ID: &str =
-    "0xef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
-pub const USDC_USD_FEED_ID: &str =
-    "0xeaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a";
-pub const MAX_AGE: u64 = 100;
 pub const ANCHOR_DISCRIMINATOR: usize = 8;
+pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+pub const MAX_DEPOSIT_POSITIONS: usize = 8;
+pub const MAX_BORROW_POSITIONS: usize = 5;
+pub const MAX_BANKS_PER_MARKET: usize = 32;
+// Base units every bank is seeded with when it is created
+pub const MIN_SEED_DEPOSIT: u64 = 1_000;
+// Highest borrow rate an interest rate model can reach, in basis points. Keeps the borrow index
+// from overflowing when a bank goes a long time without being refreshed.
+pub const MAX_BORROW_RATE: u64 = 50_000;
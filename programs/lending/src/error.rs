@@ -16,4 +16,68 @@ pub enum ErrorCode {
     MathOverflow,
     #[msg("No deposits")]
     NoDeposits,
+    #[msg("Invalid interest rate model")]
+    InvalidInterestRateModel,
+    #[msg("Bank must be refreshed in the current slot")]
+    BankStale,
+    #[msg("Maximum number of positions reached")]
+    PositionLimitReached,
+    #[msg("No position in this bank")]
+    PositionNotFound,
+    #[msg("Bank and price accounts don't match the user's positions")]
+    InvalidHealthAccounts,
+    #[msg("Withdrawal would leave borrows above the loan-to-value limit")]
+    WithdrawalExceedsHealth,
+    #[msg("Invalid oracle configuration")]
+    InvalidOracleConfig,
+    #[msg("Price account doesn't match the bank's oracle")]
+    InvalidOracleAccount,
+    #[msg("Oracle price must be positive")]
+    InvalidPrice,
+    #[msg("Oracle confidence interval is too wide")]
+    PriceConfidenceTooWide,
+    #[msg("Oracle price is too old")]
+    StalePrice,
+    #[msg("Primary and fallback oracle prices diverge too much")]
+    PriceDivergence,
+    #[msg("Test oracles are not enabled in this build")]
+    TestOracleDisabled,
+    #[msg("Collateral and borrowed banks must be different")]
+    InvalidLiquidationBanks,
+    #[msg("Liquidation would repay or seize nothing")]
+    InvalidLiquidationAmount,
+    #[msg("Seized collateral is below the liquidator's minimum")]
+    LiquidationSlippage,
+    #[msg("Signer is not allowed to perform this action")]
+    Unauthorized,
+    #[msg("Lending market can't hold more banks")]
+    MarketFull,
+    #[msg("Invalid bank configuration")]
+    InvalidBankConfig,
+    #[msg("Operation is paused")]
+    OperationPaused,
+    #[msg("Unknown flags")]
+    InvalidFlags,
+    #[msg("Deposit would exceed the bank's deposit limit")]
+    DepositLimitExceeded,
+    #[msg("Borrow would exceed the bank's borrow limit")]
+    BorrowLimitExceeded,
+    #[msg("Deposit would exceed the bank's deposit value limit")]
+    DepositValueLimitExceeded,
+    #[msg("Borrow would exceed the bank's borrow value limit")]
+    BorrowValueLimitExceeded,
+    #[msg("Fee receiver doesn't match the bank's")]
+    InvalidFeeReceiver,
+    #[msg("No protocol fees available to collect")]
+    NoFeesToCollect,
+    #[msg("Seed deposit is below the minimum")]
+    SeedDepositTooSmall,
+    #[msg("Native SOL is only supported by wrapped SOL banks")]
+    NotNativeMint,
+    #[msg("User token account is required")]
+    MissingUserTokenAccount,
+    #[msg("Mint has a freeze authority or an unsupported extension")]
+    UnsupportedMint,
+    #[msg("Amount is too small to be worth a share")]
+    ZeroShares,
 }
@@ -0,0 +1,152 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    error::ErrorCode,
+    math::{Decimal, BPS_SCALE},
+    oracle::{get_bank_price, token_value, OraclePrice},
+    Bank, User,
+};
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Health {
+    // Deposits weighted by each bank's max loan-to-value
+    pub borrow_limit: Decimal,
+    // Deposits weighted by each bank's liquidation threshold
+    pub liquidation_limit: Decimal,
+    // Borrows weighted by each bank's borrow factor
+    pub borrow_value: Decimal,
+}
+
+impl Health {
+    pub fn can_borrow(&self) -> bool {
+        self.borrow_value <= self.borrow_limit
+    }
+
+    pub fn is_liquidatable(&self) -> bool {
+        self.borrow_value > self.liquidation_limit
+    }
+
+    // Liquidation limit over weighted borrows in basis points, saturating when there is no debt
+    pub fn health_factor(&self) -> Result<u64> {
+        if self.borrow_value == Decimal::zero() {
+            return Ok(u64::MAX);
+        }
+
+        Ok(self
+            .liquidation_limit
+            .try_mul(Decimal::from(BPS_SCALE))?
+            .try_div(self.borrow_value)?
+            .try_floor_u64()
+            .unwrap_or(u64::MAX))
+    }
+}
+
+// Values every position of `user` using the accounts passed in `remaining_accounts`: each bank
+// followed by its primary oracle and, when configured, its fallback oracle. Every bank the user
+// has a position in must be present exactly once, extra banks are priced but add nothing. The
+// first account that isn't a bank ends the list, anything after it is left to the transfer. Banks
+// already loaded by the instruction are passed in `loaded_banks` so that the in-memory state is
+// used instead of the serialized account.
+pub fn calculate_health(
+    user: &User,
+    remaining_accounts: &[AccountInfo],
+    loaded_banks: &[(Pubkey, &Bank)],
+    clock: &Clock,
+) -> Result<Health> {
+    let (health, _) = calculate_health_with_prices(user, remaining_accounts, loaded_banks, clock)?;
+    Ok(health)
+}
+
+// Same as `calculate_health`, also returning the price read for every bank
+pub fn calculate_health_with_prices(
+    user: &User,
+    remaining_accounts: &[AccountInfo],
+    loaded_banks: &[(Pubkey, &Bank)],
+    clock: &Clock,
+) -> Result<(Health, Vec<(Pubkey, OraclePrice)>)> {
+    let mut accounts = remaining_accounts.iter();
+    let mut prices: Vec<(Pubkey, OraclePrice)> = Vec::new();
+    let mut health = Health::default();
+
+    while let Some(bank_info) = accounts.next() {
+        if *bank_info.owner != crate::ID {
+            break;
+        }
+
+        let bank_key = &bank_info.key();
+
+        if prices.iter().any(|(key, _)| key == bank_key) {
+            return Err(ErrorCode::InvalidHealthAccounts.into());
+        }
+
+        let loaded_bank;
+        let bank = match loaded_banks.iter().find(|(key, _)| key == bank_key) {
+            Some((_, bank)) => *bank,
+            None => {
+                loaded_bank = load_account::<Bank>(bank_info)?;
+                &loaded_bank
+            }
+        };
+
+        if bank.lending_market != user.lending_market {
+            return Err(ErrorCode::InvalidHealthAccounts.into());
+        }
+
+        if bank.is_stale(clock) {
+            return Err(ErrorCode::BankStale.into());
+        }
+
+        let price = get_bank_price(bank, &mut accounts, clock)?;
+        prices.push((*bank_key, price));
+
+        let deposit_shares = user.deposit_shares(bank_key);
+        if deposit_shares > 0 {
+            let value = token_value(
+                Decimal::from(deposit_shares).try_mul(bank.supply_index())?,
+                price.collateral_price(),
+                bank.mint_decimals,
+            )?;
+
+            health.borrow_limit = health
+                .borrow_limit
+                .try_add(value.try_mul(Decimal::from_bps(bank.max_ltw))?)?;
+            health.liquidation_limit = health
+                .liquidation_limit
+                .try_add(value.try_mul(Decimal::from_bps(bank.liquidation_threshold))?)?;
+        }
+
+        let borrow_shares = user.borrow_shares(bank_key);
+        if borrow_shares > 0 {
+            let value = token_value(
+                Decimal::from(borrow_shares).try_mul(bank.borrow_index())?,
+                price.debt_price()?,
+                bank.mint_decimals,
+            )?;
+
+            health.borrow_value = health
+                .borrow_value
+                .try_add(value.try_mul(Decimal::from_bps(bank.borrow_factor))?)?;
+        }
+    }
+
+    // A position left unpriced would make the user look healthier than they are
+    let all_priced = user
+        .deposits
+        .iter()
+        .chain(user.borrows.iter())
+        .all(|position| prices.iter().any(|(key, _)| *key == position.bank));
+    if !all_priced {
+        return Err(ErrorCode::InvalidHealthAccounts.into());
+    }
+
+    Ok((health, prices))
+}
+
+pub(crate) fn load_account<T: AccountDeserialize + Owner>(info: &AccountInfo) -> Result<T> {
+    if *info.owner != T::owner() {
+        return Err(ErrorCode::InvalidHealthAccounts.into());
+    }
+
+    let data = info.try_borrow_data()?;
+    T::try_deserialize(&mut &data[..])
+}
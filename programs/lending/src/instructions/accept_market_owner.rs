@@ -0,0 +1,23 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, LendingMarket};
+
+#[derive(Accounts)]
+pub struct AcceptMarketOwner<'info> {
+    pub pending_owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = pending_owner @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+}
+
+pub fn handler_accept_market_owner(ctx: Context<AcceptMarketOwner>) -> Result<()> {
+    let lending_market = &mut ctx.accounts.lending_market;
+
+    lending_market.owner = lending_market.pending_owner;
+    lending_market.pending_owner = Pubkey::default();
+
+    Ok(())
+}
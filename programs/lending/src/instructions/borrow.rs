@@ -1,38 +1,50 @@
-use std::f32::consts::E;
-
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface},
 };
-use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
 
-use crate::{error::ErrorCode, Bank, User, MAX_AGE, SOL_USD_FEED_ID, USDC_USD_FEED_ID};
+use crate::{
+    check_operation,
+    error::ErrorCode,
+    health::calculate_health_with_prices,
+    math::{amount_to_shares, Decimal, Rounding},
+    native::{check_native_mint, unwrap_native},
+    token::transfer_tokens,
+    Bank, LendingMarket, Operation, User,
+};
 
 #[derive(Accounts)]
 pub struct Borrow<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
+    pub lending_market: Account<'info, LendingMarket>,
+
     pub mint: InterfaceAccount<'info, Mint>,
 
     #[account(
         mut,
-        seeds = [mint.key().as_ref()],
+        seeds = [b"bank", lending_market.key().as_ref(), mint.key().as_ref()],
         bump = bank.bank_bump
     )]
     pub bank: Account<'info, Bank>,
 
     #[account(
         mut,
-        seeds = [b"treasury", mint.key().as_ref()],
+        seeds = [b"treasury", bank.key().as_ref()],
         bump = bank.treasury_bump
     )]
     pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
-        seeds = [signer.key().as_ref()],
+        seeds = [
+            b"obligation",
+            lending_market.key().as_ref(),
+            signer.key().as_ref(),
+            &user_account.id.to_le_bytes(),
+        ],
         bump = user_account.bump
     )]
     pub user_account: Account<'info, User>,
@@ -46,111 +58,130 @@ pub struct Borrow<'info> {
     )]
     pub user_token_account: InterfaceAccount<'info, TokenAccount>,
 
-    pub price_update: Account<'info, PriceUpdateV2>,
+    // Receives the host's share of the origination fee, the protocol keeps all of it otherwise. Any
+    // account of the mint is accepted so front ends can name their own, a borrower naming theirs
+    // just gets `host_fee_share` of the fee back.
+    #[account(
+        mut,
+        token::mint = mint,
+    )]
+    pub host_fee_receiver: Option<InterfaceAccount<'info, TokenAccount>>,
 
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
-pub fn handler_borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
+pub fn handler_borrow<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Borrow<'info>>,
+    amount: u64,
+    use_native: bool,
+) -> Result<()> {
     let bank = &mut ctx.accounts.bank;
     let user = &mut ctx.accounts.user_account;
 
-    // Handles token borrowing from the user, calculating and assigning proportional shares to the bank
-    let price_update = &mut ctx.accounts.price_update;
-
-    let total_collateral: u64;
-
-    // To check what asset the user needs for collateral
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            let sol_feed_id = get_feed_id_from_hex(SOL_USD_FEED_ID)?;
-            let sol_price =
-                price_update.get_price_no_older_than(&Clock::get()?, MAX_AGE, &sol_feed_id)?;
-            let new_value = calculate_accrued_interest(
-                user.deposited_sol,
-                bank.interest_rate,
-                user.last_updated,
-            )?;
-            total_collateral = sol_price.price as u64 * new_value;
-        }
-        _ => {
-            let usdc_feed_id = get_feed_id_from_hex(USDC_USD_FEED_ID)?;
-            let usdc_price =
-                price_update.get_price_no_older_than(&Clock::get()?, MAX_AGE, &usdc_feed_id)?;
-            let new_value = calculate_accrued_interest(
-                user.deposited_usdc,
-                bank.interest_rate,
-                user.last_updated,
-            )?;
-            total_collateral = usdc_price.price as u64 * new_value;
-        }
+    check_operation(
+        ctx.accounts.lending_market.flags,
+        bank.flags,
+        Operation::Borrow,
+    )?;
+
+    if use_native {
+        check_native_mint(&bank.mint_address)?;
     }
 
-    let borrowable_amount = total_collateral
-        .checked_mul(bank.liquidation_threshold)
-        .unwrap();
+    let clock = Clock::get()?;
+    if bank.is_stale(&clock) {
+        return Err(ErrorCode::BankStale.into());
+    }
 
-    if borrowable_amount < amount {
-        return Err(ErrorCode::OverBorrowableAmount.into()); // Borrowing amount exceeds collateral
+    // The origination fee is added on top of the borrowed amount
+    let (fee, mut host_fee) = bank.borrow_fees(amount)?;
+    if ctx.accounts.host_fee_receiver.is_none() {
+        host_fee = 0;
     }
 
-    // Make transfer to the user
-    let transfer_cpi_accounts = TransferChecked {
-        from: ctx.accounts.bank_token_account.to_account_info(),
-        to: ctx.accounts.user_token_account.to_account_info(),
-        authority: ctx.accounts.bank_token_account.to_account_info(),
-        mint: ctx.accounts.mint.to_account_info(),
-    };
+    // Record the new debt first so the health check covers it
+    let debt = amount.checked_add(fee).ok_or(ErrorCode::MathOverflow)?;
+    let user_shares = amount_to_shares(debt, bank.borrow_index(), Rounding::Up)?;
+    if user_shares == 0 {
+        return Err(ErrorCode::ZeroShares.into());
+    }
 
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let mint_key = ctx.accounts.mint.key();
-    let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", mint_key.as_ref(), &[bank.treasury_bump]]];
+    user.add_borrow_shares(bank.key(), user_shares)?;
 
-    let cpi_context = CpiContext::new_with_signer(cpi_program, transfer_cpi_accounts, signer_seeds);
+    // Value every deposit and borrow of the user, remaining accounts hold each bank and its price accounts
+    let (health, prices) =
+        calculate_health_with_prices(user, ctx.remaining_accounts, &[(bank.key(), bank)], &clock)?;
+
+    if !health.can_borrow() {
+        return Err(ErrorCode::OverBorrowableAmount.into()); // Borrowing amount exceeds collateral
+    }
+
+    user.health_factor = health.health_factor()?;
+
+    // Make transfer to the user, transfer hook accounts follow the health accounts
+    let bank_key = bank.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", bank_key.as_ref(), &[bank.treasury_bump]]];
     let decimals = ctx.accounts.mint.decimals;
 
     // Transfer the borrowed tokens to the user's account
-    transfer_checked(cpi_context, amount, decimals)?;
-
-    // Update the state of the user and bank
-    if bank.total_borrowed == 0 {
-        bank.total_borrowed = amount;
-        bank.total_borrowed_shares = amount;
+    transfer_tokens(
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.user_token_account.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.remaining_accounts,
+        amount,
+        decimals,
+        signer_seeds,
+    )?;
+
+    // Closing the wrapped SOL account pays everything in it out as lamports, including wrapped SOL
+    // the user held before this instruction
+    if use_native {
+        unwrap_native(
+            ctx.accounts.signer.to_account_info(),
+            ctx.accounts.user_token_account.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        )?;
     }
 
-    let borrow_ratio = amount.checked_div(bank.total_borrowed).unwrap();
-    let user_shares = bank
-        .total_borrowed_shares
-        .checked_mul(borrow_ratio)
-        .unwrap();
-
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            user.borrowed_usdc += amount;
-            user.borrowed_usdc_shares += user_shares;
-        }
-        _ => {
-            user.borrowed_sol += amount;
-            user.borrowed_sol_shares += user_shares;
+    if let Some(host_fee_receiver) = &ctx.accounts.host_fee_receiver {
+        if host_fee > 0 {
+            transfer_tokens(
+                &ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.bank_token_account.to_account_info(),
+                ctx.accounts.mint.to_account_info(),
+                host_fee_receiver.to_account_info(),
+                ctx.accounts.bank_token_account.to_account_info(),
+                ctx.remaining_accounts,
+                host_fee,
+                decimals,
+                signer_seeds,
+            )?;
         }
     }
 
-    user.last_updated_borrowed = Clock::get()?.unix_timestamp;
+    // Update the state of the bank, the protocol's part of the fee is owed back with the debt
+    bank.total_borrowed_shares = bank
+        .total_borrowed_shares
+        .checked_add(user_shares)
+        .ok_or(ErrorCode::MathOverflow)?;
+    bank.accumulated_protocol_fees = bank
+        .protocol_fees()
+        .try_add(Decimal::from(fee - host_fee))?
+        .to_scaled_val()?;
+    bank.sync_totals()?;
+
+    let (_, price) = prices
+        .iter()
+        .find(|(key, _)| *key == bank_key)
+        .ok_or(ErrorCode::InvalidHealthAccounts)?;
+    bank.check_borrow_limits(price.price)?;
+
+    user.last_updated_borrowed = clock.unix_timestamp;
 
     Ok(())
 }
-
-pub fn calculate_accrued_interest(
-    deposited: u64,
-    interest_rate: u64,
-    last_updated: i64,
-) -> Result<u64> {
-    let current_time = Clock::get()?.unix_timestamp;
-    let time_difference = current_time - last_updated;
-    let new_value =
-        (deposited as f64 * E.powf(interest_rate as f32 * time_difference as f32) as f64) as u64;
-
-    Ok(new_value)
-}
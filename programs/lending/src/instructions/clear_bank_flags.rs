@@ -0,0 +1,30 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, Bank, LendingMarket, ALL_FLAGS};
+
+#[derive(Accounts)]
+pub struct ClearBankFlags<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        constraint = admin.key() == lending_market.owner
+            || admin.key() == lending_market.risk_admin @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    #[account(
+        mut,
+        has_one = lending_market,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+pub fn handler_clear_bank_flags(ctx: Context<ClearBankFlags>, flags: u64) -> Result<()> {
+    if flags & !ALL_FLAGS != 0 {
+        return Err(ErrorCode::InvalidFlags.into());
+    }
+
+    ctx.accounts.bank.flags &= !flags;
+
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, LendingMarket, ALL_FLAGS};
+
+#[derive(Accounts)]
+pub struct ClearMarketFlags<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = admin.key() == lending_market.owner
+            || admin.key() == lending_market.risk_admin @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+}
+
+pub fn handler_clear_market_flags(ctx: Context<ClearMarketFlags>, flags: u64) -> Result<()> {
+    if flags & !ALL_FLAGS != 0 {
+        return Err(ErrorCode::InvalidFlags.into());
+    }
+
+    ctx.accounts.lending_market.flags &= !flags;
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+
+use crate::{error::ErrorCode, math::Decimal, token::transfer_tokens, Bank, LendingMarket};
+
+#[derive(Accounts)]
+pub struct CollectFees<'info> {
+    pub fee_admin: Signer<'info>,
+
+    #[account(
+        has_one = fee_admin @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"bank", lending_market.key().as_ref(), mint.key().as_ref()],
+        bump = bank.bank_bump,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", bank.key().as_ref()],
+        bump = bank.treasury_bump
+    )]
+    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = bank.fee_receiver @ ErrorCode::InvalidFeeReceiver,
+    )]
+    pub fee_receiver: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+pub fn handler_collect_fees<'info>(
+    ctx: Context<'_, '_, 'info, 'info, CollectFees<'info>>,
+) -> Result<()> {
+    let bank = &mut ctx.accounts.bank;
+    bank.refresh(&Clock::get()?)?;
+
+    // Whatever the treasury and outstanding loans hold beyond what depositors are owed belongs to
+    // the protocol. Origination fees are part of the loans as soon as they are made, so they can be
+    // collected right away, but never by taking the treasury below deposits net of borrows.
+    let depositor_liquidity = bank.total_deposits.saturating_sub(bank.total_borrowed);
+    let available = ctx
+        .accounts
+        .bank_token_account
+        .amount
+        .saturating_sub(depositor_liquidity);
+
+    let amount = bank.protocol_fees().try_floor_u64()?.min(available);
+    if amount == 0 {
+        return Err(ErrorCode::NoFeesToCollect.into());
+    }
+
+    bank.accumulated_protocol_fees = bank
+        .protocol_fees()
+        .try_sub(Decimal::from(amount))?
+        .to_scaled_val()?;
+
+    // Remaining accounts carry the mint's transfer hook accounts
+    let bank_key = bank.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", bank_key.as_ref(), &[bank.treasury_bump]]];
+
+    transfer_tokens(
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.fee_receiver.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.remaining_accounts,
+        amount,
+        ctx.accounts.mint.decimals,
+        signer_seeds,
+    )?;
+
+    Ok(())
+}
@@ -1,99 +1,173 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+    token_interface::{mint_to, Mint, MintTo, TokenAccount, TokenInterface},
 };
 
-use crate::{Bank, User};
+use crate::{
+    check_operation,
+    error::ErrorCode,
+    math::{amount_to_shares, Rounding},
+    native::{check_native_mint, wrap_native},
+    oracle::get_bank_price,
+    token::transfer_tokens,
+    Bank, LendingMarket, Operation,
+};
 
 #[derive(Accounts)]
 pub struct Deposit<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
-    pub mint: InterfaceAccount<'info, Mint>,
+    pub lending_market: Account<'info, LendingMarket>,
+
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
         mut,
-        seeds = [mint.key().as_ref()],
+        seeds = [b"bank", lending_market.key().as_ref(), mint.key().as_ref()],
         bump = bank.bank_bump
     )]
-    pub bank: Account<'info, Bank>,
+    pub bank: Box<Account<'info, Bank>>,
 
     #[account(
         mut,
-        seeds = [b"treasury", mint.key().as_ref()],
+        seeds = [b"treasury", bank.key().as_ref()],
         bump = bank.treasury_bump
     )]
-    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub bank_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        seeds = [signer.key().as_ref()],
-        bump = user_account.bump
+        seeds = [b"receipt_mint", bank.key().as_ref()],
+        bump = bank.receipt_mint_bump
     )]
-    pub user_account: Account<'info, User>,
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
 
+    // Not needed when depositing native SOL
     #[account(
         mut,
         associated_token::mint = mint,
         associated_token::authority = signer,
         associated_token::token_program = token_program,
     )]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    #[account(
+        init_if_needed,
+        payer = signer,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+    )]
+    pub user_receipt_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
-pub fn handler_deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
-    // This handles the deposit of tokens
-    let transfer_cpi_accounts = TransferChecked {
-        from: ctx.accounts.user_token_account.to_account_info(),
-        to: ctx.accounts.bank_token_account.to_account_info(),
-        authority: ctx.accounts.signer.to_account_info(),
-        mint: ctx.accounts.mint.to_account_info(),
-    };
-
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_context = CpiContext::new(cpi_program, transfer_cpi_accounts);
+pub fn handler_deposit<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Deposit<'info>>,
+    amount: u64,
+    use_native: bool,
+) -> Result<()> {
+    check_operation(
+        ctx.accounts.lending_market.flags,
+        ctx.accounts.bank.flags,
+        Operation::Deposit,
+    )?;
+
+    let clock = Clock::get()?;
+    if ctx.accounts.bank.is_stale(&clock) {
+        return Err(ErrorCode::BankStale.into());
+    }
 
-    let decimals = ctx.accounts.mint.decimals;
+    // Transfer fees are taken out of the deposit, so only what the treasury receives is credited
+    let balance_before = ctx.accounts.bank_token_account.amount;
+
+    // This handles the deposit of tokens, native SOL is wrapped straight into the treasury
+    if use_native {
+        check_native_mint(&ctx.accounts.mint.key())?;
+
+        wrap_native(
+            ctx.accounts.signer.to_account_info(),
+            ctx.accounts.bank_token_account.to_account_info(),
+            amount,
+            ctx.accounts.system_program.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+    } else {
+        let user_token_account = ctx
+            .accounts
+            .user_token_account
+            .as_ref()
+            .ok_or(ErrorCode::MissingUserTokenAccount)?;
+
+        // Transfer hook accounts follow the price accounts in the remaining accounts
+        transfer_tokens(
+            &ctx.accounts.token_program.to_account_info(),
+            user_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.bank_token_account.to_account_info(),
+            ctx.accounts.signer.to_account_info(),
+            ctx.remaining_accounts,
+            amount,
+            ctx.accounts.mint.decimals,
+            &[],
+        )?;
+    }
 
-    transfer_checked(cpi_context, amount, decimals)?;
+    ctx.accounts.bank_token_account.reload()?;
+    let received = ctx
+        .accounts
+        .bank_token_account
+        .amount
+        .checked_sub(balance_before)
+        .ok_or(ErrorCode::MathOverflow)?;
 
     // Update the bank's state and handles the accounting
     let bank = &mut ctx.accounts.bank;
 
-    if bank.total_deposits == 0 {
-        bank.total_deposits = amount;
-        bank.total_deposit_shares = amount;
+    let user_shares = amount_to_shares(received, bank.supply_index(), Rounding::Down)?;
+    if user_shares == 0 {
+        return Err(ErrorCode::ZeroShares.into());
     }
 
-    let deposit_ratio = amount.checked_div(bank.total_deposits).unwrap();
-    let user_shares = bank
+    bank.total_deposit_shares = bank
         .total_deposit_shares
-        .checked_mul(deposit_ratio)
-        .unwrap();
-
-    let user = &mut ctx.accounts.user_account;
-
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            user.deposited_usdc += amount;
-            user.deposited_usdc_shares = user_shares;
-        }
-        _ => {
-            user.deposited_sol += amount;
-            user.deposited_sol_shares = user_shares;
-        }
-    }
+        .checked_add(user_shares)
+        .ok_or(ErrorCode::MathOverflow)?;
+    bank.sync_totals()?;
+
+    // A USD cap needs the bank's price accounts passed as remaining accounts
+    let price = if bank.deposit_limit_usd > 0 {
+        let price = get_bank_price(bank, &mut ctx.remaining_accounts.iter(), &clock)?;
+        Some(price.price)
+    } else {
+        None
+    };
+    bank.check_deposit_limits(price)?;
+
+    // Hand out the new shares as receipt tokens, signed by the bank
+    let mint_to_accounts = MintTo {
+        mint: ctx.accounts.receipt_mint.to_account_info(),
+        to: ctx.accounts.user_receipt_token_account.to_account_info(),
+        authority: bank.to_account_info(),
+    };
 
-    bank.total_deposits += amount;
-    bank.total_deposit_shares += user_shares;
+    let lending_market_key = ctx.accounts.lending_market.key();
+    let mint_key = ctx.accounts.mint.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"bank",
+        lending_market_key.as_ref(),
+        mint_key.as_ref(),
+        &[bank.bank_bump],
+    ]];
 
-    user.last_updated = Clock::get()?.unix_timestamp;
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_context = CpiContext::new_with_signer(cpi_program, mint_to_accounts, signer_seeds);
+    mint_to(cpi_context, user_shares)?;
 
     Ok(())
 }
@@ -0,0 +1,72 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+
+use crate::{Bank, LendingMarket, User};
+
+#[derive(Accounts)]
+pub struct DepositCollateral<'info> {
+    pub signer: Signer<'info>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    #[account(
+        has_one = lending_market,
+        has_one = receipt_mint,
+    )]
+    pub bank: Box<Account<'info, Bank>>,
+
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", bank.key().as_ref()],
+        bump = bank.collateral_vault_bump
+    )]
+    pub collateral_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = receipt_mint,
+        token::authority = signer,
+    )]
+    pub user_receipt_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"obligation",
+            lending_market.key().as_ref(),
+            signer.key().as_ref(),
+            &user_account.id.to_le_bytes(),
+        ],
+        bump = user_account.bump
+    )]
+    pub user_account: Box<Account<'info, User>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// Locks `amount` receipt tokens in the bank's collateral vault and credits them to the user
+// account as deposit shares
+pub fn handler_deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Result<()> {
+    let transfer_cpi_accounts = TransferChecked {
+        from: ctx.accounts.user_receipt_token_account.to_account_info(),
+        to: ctx.accounts.collateral_vault.to_account_info(),
+        authority: ctx.accounts.signer.to_account_info(),
+        mint: ctx.accounts.receipt_mint.to_account_info(),
+    };
+
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_context = CpiContext::new(cpi_program, transfer_cpi_accounts);
+    let decimals = ctx.accounts.receipt_mint.decimals;
+
+    transfer_checked(cpi_context, amount, decimals)?;
+
+    let user = &mut ctx.accounts.user_account;
+    user.add_deposit_shares(ctx.accounts.bank.key(), amount)?;
+    user.last_updated = Clock::get()?.unix_timestamp;
+
+    Ok(())
+}
@@ -1,20 +1,31 @@
 use anchor_lang::prelude::*;
-use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use anchor_spl::token_interface::{mint_to, Mint, MintTo, TokenAccount, TokenInterface};
 
-use crate::{Bank, ANCHOR_DISCRIMINATOR};
+use crate::{
+    error::ErrorCode,
+    math::{amount_to_shares, Rounding, WAD},
+    token::{check_mint_extensions, transfer_tokens},
+    Bank, BankConfig, LendingMarket, ANCHOR_DISCRIMINATOR, MAX_BANKS_PER_MARKET, MIN_SEED_DEPOSIT,
+};
 
 #[derive(Accounts)]
 pub struct InitializeBank<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
+    #[account(
+        mut,
+        constraint = lending_market.owner == signer.key() @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Box<Account<'info, LendingMarket>>,
+
     pub mint: InterfaceAccount<'info, Mint>,
 
     #[account(
         init,
         payer = signer,
         space = ANCHOR_DISCRIMINATOR + Bank::INIT_SPACE,
-        seeds = [mint.key().as_ref()],
+        seeds = [b"bank", lending_market.key().as_ref(), mint.key().as_ref()],
         bump,
     )]
     pub bank: Box<Account<'info, Bank>>,
@@ -24,36 +35,127 @@ pub struct InitializeBank<'info> {
         token::mint = mint,
         token::authority = bank_token_account,
         payer = signer,
-        seeds = [b"treasury", mint.key().as_ref()],
+        seeds = [b"treasury", bank.key().as_ref()],
         bump,
     )]
     pub bank_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
+    // Receipt tokens represent deposit shares one to one
+    #[account(
+        init,
+        mint::decimals = mint.decimals,
+        mint::authority = bank,
+        payer = signer,
+        seeds = [b"receipt_mint", bank.key().as_ref()],
+        bump,
+    )]
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    // Holds the receipt tokens users put up as collateral
+    #[account(
+        init,
+        token::mint = receipt_mint,
+        token::authority = collateral_vault,
+        payer = signer,
+        seeds = [b"collateral_vault", bank.key().as_ref()],
+        bump,
+    )]
+    pub collateral_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    // Funds the seed deposit
+    #[account(
+        mut,
+        token::mint = mint,
+        token::authority = signer,
+    )]
+    pub signer_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
-pub fn handler_initialize_bank(
-    ctx: Context<InitializeBank>,
-    liquidation_threshold: u64,
-    max_ltw: u64,
+pub fn handler_initialize_bank<'info>(
+    ctx: Context<'_, '_, 'info, 'info, InitializeBank<'info>>,
+    config: BankConfig,
+    seed_amount: u64,
 ) -> Result<()> {
-    ctx.accounts.bank.set_inner(Bank {
-        authority: ctx.accounts.signer.key(),
+    config.validate()?;
+    check_mint_extensions(&ctx.accounts.mint.to_account_info())?;
+
+    if seed_amount < MIN_SEED_DEPOSIT {
+        return Err(ErrorCode::SeedDepositTooSmall.into());
+    }
+
+    let clock = Clock::get()?;
+
+    // Register the bank so the market keeps track of everything it lists
+    let lending_market = &mut ctx.accounts.lending_market;
+    if lending_market.banks.len() >= MAX_BANKS_PER_MARKET {
+        return Err(ErrorCode::MarketFull.into());
+    }
+    lending_market.banks.push(ctx.accounts.bank.key());
+
+    // Risk parameters, oracles, limits and fees all come from the config
+    let bank = &mut ctx.accounts.bank;
+    bank.set_inner(Bank {
+        lending_market: ctx.accounts.lending_market.key(),
         mint_address: ctx.accounts.mint.key(),
-        total_deposits: 0,
-        total_deposit_shares: 0,
-        liquidation_threshold,
-        liquidation_bonus: 0,
-        liquidation_close_factor: 0,
-        max_ltw,
-        last_updated: 0,
-        interest_rate: 0.05 as u64,
-        total_borrowed: 0,
-        total_borrowed_shares: 0,
+        mint_decimals: ctx.accounts.mint.decimals,
+        last_updated: clock.unix_timestamp,
+        last_updated_slot: clock.slot,
+        cumulative_borrow_index: WAD as u128,
+        cumulative_supply_index: WAD as u128,
         bank_bump: ctx.bumps.bank,
         treasury_bump: ctx.bumps.bank_token_account,
+        receipt_mint: ctx.accounts.receipt_mint.key(),
+        receipt_mint_bump: ctx.bumps.receipt_mint,
+        collateral_vault_bump: ctx.bumps.collateral_vault,
+        ..Bank::default()
     });
+    bank.set_config(&config);
+
+    // Seed the bank with a deposit nobody owns. Its receipt tokens stay locked in the collateral
+    // vault outside of any user account, so the supply can never be drained back to a handful of
+    // shares whose price a first depositor could skew through rounding.
+    transfer_tokens(
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.signer_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.signer.to_account_info(),
+        ctx.remaining_accounts,
+        seed_amount,
+        ctx.accounts.mint.decimals,
+        &[],
+    )?;
+
+    // Only what arrives after a transfer fee is booked
+    ctx.accounts.bank_token_account.reload()?;
+    let received = ctx.accounts.bank_token_account.amount;
+
+    let bank = &mut ctx.accounts.bank;
+    let seed_shares = amount_to_shares(received, bank.supply_index(), Rounding::Down)?;
+    bank.total_deposit_shares = seed_shares;
+    bank.sync_totals()?;
+
+    let mint_to_accounts = MintTo {
+        mint: ctx.accounts.receipt_mint.to_account_info(),
+        to: ctx.accounts.collateral_vault.to_account_info(),
+        authority: bank.to_account_info(),
+    };
+
+    let lending_market_key = ctx.accounts.lending_market.key();
+    let mint_key = ctx.accounts.mint.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"bank",
+        lending_market_key.as_ref(),
+        mint_key.as_ref(),
+        &[bank.bank_bump],
+    ]];
+
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let cpi_context = CpiContext::new_with_signer(cpi_program, mint_to_accounts, signer_seeds);
+    mint_to(cpi_context, seed_shares)?;
 
     Ok(())
 }
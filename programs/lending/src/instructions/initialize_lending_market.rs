@@ -0,0 +1,39 @@
+use anchor_lang::prelude::*;
+
+use crate::{LendingMarket, ANCHOR_DISCRIMINATOR};
+
+#[derive(Accounts)]
+pub struct InitializeLendingMarket<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = ANCHOR_DISCRIMINATOR + LendingMarket::INIT_SPACE,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler_initialize_lending_market(
+    ctx: Context<InitializeLendingMarket>,
+    quote_currency: [u8; 32],
+) -> Result<()> {
+    // Every role starts with the owner until it hands them out
+    let owner = ctx.accounts.owner.key();
+
+    ctx.accounts.lending_market.set_inner(LendingMarket {
+        owner,
+        pending_owner: Pubkey::default(),
+        risk_admin: owner,
+        guardian: owner,
+        fee_admin: owner,
+        quote_currency,
+        flags: 0,
+        banks: Vec::new(),
+    });
+
+    Ok(())
+}
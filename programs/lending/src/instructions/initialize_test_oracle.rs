@@ -0,0 +1,51 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::{error::ErrorCode, LendingMarket, TestOracle, ANCHOR_DISCRIMINATOR};
+
+#[derive(Accounts)]
+pub struct InitializeTestOracle<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(
+        has_one = owner @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    pub mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        init,
+        payer = owner,
+        space = ANCHOR_DISCRIMINATOR + TestOracle::INIT_SPACE,
+        seeds = [b"test_oracle", lending_market.key().as_ref(), mint.key().as_ref()],
+        bump,
+    )]
+    pub test_oracle: Account<'info, TestOracle>,
+
+    pub system_program: Program<'info, System>,
+}
+
+pub fn handler_initialize_test_oracle(
+    ctx: Context<InitializeTestOracle>,
+    price: i64,
+    exponent: i32,
+    confidence: u64,
+) -> Result<()> {
+    // Anchor can't compile instructions out, so builds without the feature reject them instead.
+    // The same check guards `set_test_oracle_price`.
+    if !cfg!(feature = "test-oracle") {
+        return Err(ErrorCode::TestOracleDisabled.into());
+    }
+
+    ctx.accounts.test_oracle.set_inner(TestOracle {
+        lending_market: ctx.accounts.lending_market.key(),
+        price,
+        exponent,
+        confidence,
+        publish_time: Clock::get()?.unix_timestamp,
+    });
+
+    Ok(())
+}
@@ -1,17 +1,25 @@
 use anchor_lang::prelude::*;
 
-use crate::{User, ANCHOR_DISCRIMINATOR};
+use crate::{LendingMarket, User, ANCHOR_DISCRIMINATOR};
 
 #[derive(Accounts)]
+#[instruction(id: u64)]
 pub struct InitializeUser<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
+    pub lending_market: Account<'info, LendingMarket>,
+
     #[account(
         init,
         payer = signer,
         space = ANCHOR_DISCRIMINATOR + User::INIT_SPACE,
-        seeds = [signer.key().as_ref()],
+        seeds = [
+            b"obligation",
+            lending_market.key().as_ref(),
+            signer.key().as_ref(),
+            &id.to_le_bytes(),
+        ],
         bump,
     )]
     pub user_account: Account<'info, User>,
@@ -19,18 +27,13 @@ pub struct InitializeUser<'info> {
     pub system_program: Program<'info, System>,
 }
 
-pub fn handler_initialize_user(ctx: Context<InitializeUser>, usdc_address: Pubkey) -> Result<()> {
+pub fn handler_initialize_user(ctx: Context<InitializeUser>, id: u64) -> Result<()> {
     ctx.accounts.user_account.set_inner(User {
         owner: ctx.accounts.signer.key(),
-        deposited_sol: 0,
-        deposited_sol_shares: 0,
-        borrowed_sol: 0,
-        borrowed_sol_shares: 0,
-        deposited_usdc: 0,
-        deposited_usdc_shares: 0,
-        borrowed_usdc: 0,
-        borrowed_usdc_shares: 0,
-        usdc_address,
+        lending_market: ctx.accounts.lending_market.key(),
+        id,
+        deposits: Vec::new(),
+        borrows: Vec::new(),
         health_factor: 0,
         last_updated: 0,
         last_updated_borrowed: 0,
@@ -1,54 +1,84 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+    token_interface::{burn, Burn, Mint, TokenAccount, TokenInterface},
 };
-use pyth_solana_receiver_sdk::price_update::{get_feed_id_from_hex, PriceUpdateV2};
 
-use crate::{error::ErrorCode, Bank, User, MAX_AGE, SOL_USD_FEED_ID, USDC_USD_FEED_ID};
-
-use super::calculate_accrued_interest;
+use crate::{
+    check_operation,
+    error::ErrorCode,
+    health::{calculate_health, calculate_health_with_prices},
+    math::{amount_to_shares, shares_to_amount, Decimal, Rounding},
+    oracle::{token_amount, token_value, OraclePrice},
+    token::{amount_with_transfer_fee, transfer_tokens},
+    Bank, LendingMarket, Operation, User,
+};
 
 #[derive(Accounts)]
 pub struct Liquidate<'info> {
     #[account(mut)]
     pub liquidator: Signer<'info>,
 
-    pub price_update: Box<Account<'info, PriceUpdateV2>>,
+    pub lending_market: Box<Account<'info, LendingMarket>>,
+
+    // Either mint can belong to Token or Token-2022, so each side has its own token program
+    #[account(mint::token_program = collateral_token_program)]
     pub collateral_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(mint::token_program = borrowed_token_program)]
     pub borrowed_mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
         mut,
-        seeds = [collateral_mint.key().as_ref()],
+        seeds = [b"bank", lending_market.key().as_ref(), collateral_mint.key().as_ref()],
         bump = collateral_bank.bank_bump
     )]
     pub collateral_bank: Box<Account<'info, Bank>>,
 
     #[account(
         mut,
-        seeds = [borrowed_mint.key().as_ref()],
+        seeds = [b"bank", lending_market.key().as_ref(), borrowed_mint.key().as_ref()],
         bump = borrowed_bank.bank_bump
     )]
     pub borrowed_bank: Box<Account<'info, Bank>>,
 
     #[account(
         mut,
-        seeds = [b"treasury", collateral_mint.key().as_ref()],
+        seeds = [b"treasury", collateral_bank.key().as_ref()],
         bump = collateral_bank.treasury_bump
     )]
     pub collateral_bank_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        seeds = [b"treasury", borrowed_mint.key().as_ref()],
+        seeds = [b"receipt_mint", collateral_bank.key().as_ref()],
+        bump = collateral_bank.receipt_mint_bump
+    )]
+    pub collateral_receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    // Seized collateral shares are burned from here
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", collateral_bank.key().as_ref()],
+        bump = collateral_bank.collateral_vault_bump
+    )]
+    pub collateral_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury", borrowed_bank.key().as_ref()],
         bump = borrowed_bank.treasury_bump
     )]
     pub borrowed_bank_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
+    // The borrower being liquidated, any user PDA regardless of who signs
     #[account(
         mut,
-        seeds = [liquidator.key().as_ref()],
+        seeds = [
+            b"obligation",
+            lending_market.key().as_ref(),
+            user_account.owner.as_ref(),
+            &user_account.id.to_le_bytes(),
+        ],
         bump = user_account.bump
     )]
     pub user_account: Box<Account<'info, User>>,
@@ -58,7 +88,7 @@ pub struct Liquidate<'info> {
        payer = liquidator,
        associated_token::mint = collateral_mint,
        associated_token::authority = liquidator,
-       associated_token::token_program = token_program,
+       associated_token::token_program = collateral_token_program,
     )]
     pub liquidator_collateral_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
@@ -67,122 +97,248 @@ pub struct Liquidate<'info> {
        payer = liquidator,
        associated_token::mint = borrowed_mint,
        associated_token::authority = liquidator,
-       associated_token::token_program = token_program,
+       associated_token::token_program = borrowed_token_program,
     )]
     pub liquidator_borrowed_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
-    pub token_program: Interface<'info, TokenInterface>,
+    pub collateral_token_program: Interface<'info, TokenInterface>,
+    pub borrowed_token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
 }
 
-pub fn handler_liquidate(ctx: Context<Liquidate>) -> Result<()> {
+pub fn handler_liquidate<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Liquidate<'info>>,
+    repay_amount: u64,
+    min_collateral_out: u64,
+) -> Result<()> {
     // This handles the liquidation of collateral and borrowed tokens
-    // It calculates the amount of collateral and borrowed tokens to be liquidated
-    // It transfers the tokens to the liquidator's accounts, and then updates the bank balances
-    // Finally, it updates the price update
+    // It caps the repaid debt at the close factor and converts its value into collateral plus bonus
+    // It burns the repaid debt and seized collateral shares of the borrower and updates both banks
+    // Finally, it transfers the tokens and records the borrower's new health factor
+
+    let collateral_bank_key = ctx.accounts.collateral_bank.key();
+    let borrowed_bank_key = ctx.accounts.borrowed_bank.key();
+
+    // Both banks are written back on exit, so the same bank can't be passed twice
+    if collateral_bank_key == borrowed_bank_key {
+        return Err(ErrorCode::InvalidLiquidationBanks.into());
+    }
 
     let collateral_bank = &mut ctx.accounts.collateral_bank;
-    let user = &mut ctx.accounts.user_account;
     let borrowed_bank = &mut ctx.accounts.borrowed_bank;
+    let user = &mut ctx.accounts.user_account;
 
-    let price_update = &mut ctx.accounts.price_update;
-
-    let sol_feed_id = get_feed_id_from_hex(SOL_USD_FEED_ID)?;
-    let usdc_feed_id = get_feed_id_from_hex(USDC_USD_FEED_ID)?;
-
-    let sol_price = price_update.get_price_no_older_than(&Clock::get()?, MAX_AGE, &sol_feed_id)?;
-    let usdc_price =
-        price_update.get_price_no_older_than(&Clock::get()?, MAX_AGE, &usdc_feed_id)?;
-
-    let total_collateral: u64;
-    let total_borrowed: u64;
-
-    match ctx.accounts.collateral_mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            let new_usdc = calculate_accrued_interest(
-                user.deposited_usdc,
-                collateral_bank.interest_rate,
-                user.last_updated,
-            )?;
-            total_collateral = usdc_price.price as u64 * new_usdc;
-            let new_sol = calculate_accrued_interest(
-                user.borrowed_sol,
-                borrowed_bank.interest_rate,
-                user.last_updated_borrowed,
-            )?;
-            total_borrowed = sol_price.price as u64 * new_sol;
-        }
-        _ => {
-            let new_sol = calculate_accrued_interest(
-                user.deposited_sol,
-                collateral_bank.interest_rate,
-                user.last_updated,
-            )?;
-            total_collateral = sol_price.price as u64 * new_sol;
-            let new_usdc = calculate_accrued_interest(
-                user.borrowed_usdc,
-                borrowed_bank.interest_rate,
-                user.last_updated_borrowed,
-            )?;
-            total_borrowed = usdc_price.price as u64 * new_usdc;
-        }
+    let market_flags = ctx.accounts.lending_market.flags;
+    check_operation(market_flags, collateral_bank.flags, Operation::Liquidate)?;
+    check_operation(market_flags, borrowed_bank.flags, Operation::Liquidate)?;
+
+    let clock = Clock::get()?;
+    if collateral_bank.is_stale(&clock) || borrowed_bank.is_stale(&clock) {
+        return Err(ErrorCode::BankStale.into());
     }
 
-    let health_factor = (total_collateral as f64 * collateral_bank.liquidation_threshold as f64
-        / total_borrowed as f64) as f64;
+    // Value every deposit and borrow of the user, remaining accounts hold each bank and its price accounts
+    let (health, prices) = calculate_health_with_prices(
+        user,
+        ctx.remaining_accounts,
+        &[
+            (collateral_bank_key, collateral_bank),
+            (borrowed_bank_key, borrowed_bank),
+        ],
+        &clock,
+    )?;
 
-    if health_factor >= 1.0 {
+    if !health.is_liquidatable() {
         return Err(ErrorCode::NotUnderCollaterized.into());
     }
 
-    // This transfer token to the bank
-    let transfer_to_bank = TransferChecked {
-        from: ctx
-            .accounts
-            .liquidator_borrowed_token_account
-            .to_account_info(),
-        to: ctx.accounts.borrowed_bank_token_account.to_account_info(),
-        authority: ctx.accounts.liquidator.to_account_info(),
-        mint: ctx.accounts.borrowed_mint.to_account_info(),
-    };
+    let borrowed_shares = user.borrow_shares(&borrowed_bank_key);
+    if borrowed_shares == 0 {
+        return Err(ErrorCode::NoOutstandingBorrows.into());
+    }
 
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_context = CpiContext::new(cpi_program, transfer_to_bank);
-    let decimals = ctx.accounts.borrowed_mint.decimals;
+    let deposit_shares = user.deposit_shares(&collateral_bank_key);
+    if deposit_shares == 0 {
+        return Err(ErrorCode::NoDeposits.into());
+    }
 
-    let liquidation_amount = total_borrowed
-        .checked_mul(borrowed_bank.liquidation_close_factor)
-        .unwrap();
+    let collateral_price = bank_price(&prices, &collateral_bank_key)?;
+    let borrowed_price = bank_price(&prices, &borrowed_bank_key)?;
+    let collateral_decimals = ctx.accounts.collateral_mint.decimals;
+    let borrowed_decimals = ctx.accounts.borrowed_mint.decimals;
+    let bonus = Decimal::one().try_add(Decimal::from_bps(collateral_bank.liquidation_bonus))?;
 
-    transfer_checked(cpi_context, liquidation_amount, decimals)?;
+    // At most the close factor of the debt can be repaid in one liquidation
+    let borrowed_amount = Decimal::from(borrowed_shares).try_mul(borrowed_bank.borrow_index())?;
+    let max_repay_amount = borrowed_amount
+        .try_mul(Decimal::from_bps(borrowed_bank.liquidation_close_factor))?
+        .try_floor_u64()?;
 
-    // This pays the liquidator
-    let liquidator_amount =
-        (liquidation_amount * collateral_bank.liquidation_bonus) + liquidation_amount;
+    // Only whole debt shares can be cancelled, the liquidator repays exactly what they are worth
+    let mut repaid_shares = repaid_debt_shares(
+        repay_amount.min(max_repay_amount),
+        borrowed_bank,
+        borrowed_shares,
+    )?;
+    let mut liquidation_amount =
+        shares_to_amount(repaid_shares, borrowed_bank.borrow_index(), Rounding::Up)?;
 
-    let transfer_to_liquidator = TransferChecked {
-        from: ctx.accounts.collateral_bank_token_account.to_account_info(),
-        to: ctx
-            .accounts
-            .liquidator_collateral_token_account
-            .to_account_info(),
-        authority: ctx.accounts.collateral_bank_token_account.to_account_info(),
-        mint: ctx.accounts.collateral_mint.to_account_info(),
+    // Repaid debt value plus the bonus, converted into collateral tokens
+    let seized_value = token_value(
+        Decimal::from(liquidation_amount),
+        borrowed_price,
+        borrowed_decimals,
+    )?
+    .try_mul(bonus)?;
+    let mut liquidator_amount =
+        token_amount(seized_value, collateral_price, collateral_decimals)?.try_floor_u64()?;
+
+    // Seize the collateral shares backing the liquidator's payout. When the borrower's deposit
+    // can't cover it, seize the whole deposit and only repay the debt it is worth.
+    let mut seized_shares = amount_to_shares(
+        liquidator_amount,
+        collateral_bank.supply_index(),
+        Rounding::Up,
+    )?;
+    if seized_shares > deposit_shares {
+        seized_shares = deposit_shares;
+        liquidator_amount = shares_to_amount(
+            seized_shares,
+            collateral_bank.supply_index(),
+            Rounding::Down,
+        )?;
+
+        let repaid_value = token_value(
+            Decimal::from(liquidator_amount),
+            collateral_price,
+            collateral_decimals,
+        )?
+        .try_div(bonus)?;
+        let repaid_amount = token_amount(repaid_value, borrowed_price, borrowed_decimals)?
+            .try_ceil_u64()?
+            .min(liquidation_amount);
+
+        repaid_shares = repaid_debt_shares(repaid_amount, borrowed_bank, borrowed_shares)?;
+        liquidation_amount =
+            shares_to_amount(repaid_shares, borrowed_bank.borrow_index(), Rounding::Up)?;
+    }
+
+    if liquidation_amount == 0 || liquidator_amount == 0 {
+        return Err(ErrorCode::InvalidLiquidationAmount.into());
+    }
+
+    if liquidator_amount < min_collateral_out {
+        return Err(ErrorCode::LiquidationSlippage.into());
+    }
+
+    user.remove_borrow_shares(&borrowed_bank_key, repaid_shares)?;
+    user.remove_deposit_shares(&collateral_bank_key, seized_shares)?;
+
+    borrowed_bank.total_borrowed_shares = borrowed_bank
+        .total_borrowed_shares
+        .checked_sub(repaid_shares)
+        .ok_or(ErrorCode::MathOverflow)?;
+    borrowed_bank.sync_totals()?;
+
+    collateral_bank.total_deposit_shares = collateral_bank
+        .total_deposit_shares
+        .checked_sub(seized_shares)
+        .ok_or(ErrorCode::MathOverflow)?;
+    collateral_bank.sync_totals()?;
+
+    let health = calculate_health(
+        user,
+        ctx.remaining_accounts,
+        &[
+            (collateral_bank_key, collateral_bank),
+            (borrowed_bank_key, borrowed_bank),
+        ],
+        &clock,
+    )?;
+    user.health_factor = health.health_factor()?;
+    user.last_updated = clock.unix_timestamp;
+    user.last_updated_borrowed = clock.unix_timestamp;
+
+    // Burn the receipt tokens backing the seized shares
+    let burn_accounts = Burn {
+        mint: ctx.accounts.collateral_receipt_mint.to_account_info(),
+        from: ctx.accounts.collateral_vault.to_account_info(),
+        authority: ctx.accounts.collateral_vault.to_account_info(),
     };
 
-    let cpi_program_2 = ctx.accounts.token_program.to_account_info();
-    let mint_key = ctx.accounts.collateral_mint.key();
+    let vault_seeds: &[&[&[u8]]] = &[&[
+        b"collateral_vault",
+        collateral_bank_key.as_ref(),
+        &[ctx.accounts.collateral_bank.collateral_vault_bump],
+    ]];
+
+    let cpi_program = ctx.accounts.collateral_token_program.to_account_info();
+    let cpi_context = CpiContext::new_with_signer(cpi_program, burn_accounts, vault_seeds);
+    burn(cpi_context, seized_shares)?;
+
+    // This transfer token to the bank, grossed up so the bank receives the whole repaid amount
+    // under a transfer fee. Transfer hook accounts of both mints follow the health accounts.
+    let transfer_amount = amount_with_transfer_fee(
+        &ctx.accounts.borrowed_mint.to_account_info(),
+        liquidation_amount,
+    )?;
+
+    transfer_tokens(
+        &ctx.accounts.borrowed_token_program.to_account_info(),
+        ctx.accounts
+            .liquidator_borrowed_token_account
+            .to_account_info(),
+        ctx.accounts.borrowed_mint.to_account_info(),
+        ctx.accounts.borrowed_bank_token_account.to_account_info(),
+        ctx.accounts.liquidator.to_account_info(),
+        ctx.remaining_accounts,
+        transfer_amount,
+        borrowed_decimals,
+        &[],
+    )?;
+
+    // This pays the liquidator
     let signer_seeds: &[&[&[u8]]] = &[&[
         b"treasury",
-        mint_key.as_ref(),
-        &[collateral_bank.treasury_bump],
+        collateral_bank_key.as_ref(),
+        &[ctx.accounts.collateral_bank.treasury_bump],
     ]];
 
-    let cpi_context_to_liquidator =
-        CpiContext::new_with_signer(cpi_program_2, transfer_to_liquidator, signer_seeds);
-
-    transfer_checked(cpi_context_to_liquidator, liquidator_amount, decimals)?;
+    transfer_tokens(
+        &ctx.accounts.collateral_token_program.to_account_info(),
+        ctx.accounts.collateral_bank_token_account.to_account_info(),
+        ctx.accounts.collateral_mint.to_account_info(),
+        ctx.accounts
+            .liquidator_collateral_token_account
+            .to_account_info(),
+        ctx.accounts.collateral_bank_token_account.to_account_info(),
+        ctx.remaining_accounts,
+        liquidator_amount,
+        collateral_decimals,
+        signer_seeds,
+    )?;
 
     Ok(())
 }
+
+// Debt shares cancelled by repaying `amount`, rounded down so the borrower never owes less than
+// was repaid
+fn repaid_debt_shares(amount: u64, bank: &Bank, borrowed_shares: u64) -> Result<u64> {
+    let shares =
+        amount_to_shares(amount, bank.borrow_index(), Rounding::Down)?.min(borrowed_shares);
+    if shares == 0 {
+        return Err(ErrorCode::ZeroShares.into());
+    }
+
+    Ok(shares)
+}
+
+// Liquidations convert at the oracle price itself, without the confidence adjustments
+fn bank_price(prices: &[(Pubkey, OraclePrice)], bank: &Pubkey) -> Result<Decimal> {
+    prices
+        .iter()
+        .find(|(key, _)| key == bank)
+        .map(|(_, price)| price.price)
+        .ok_or(ErrorCode::InvalidHealthAccounts.into())
+}
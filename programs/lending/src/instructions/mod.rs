@@ -1,15 +1,47 @@
+pub mod accept_market_owner;
 pub mod borrow;
+pub mod clear_bank_flags;
+pub mod clear_market_flags;
+pub mod collect_fees;
 pub mod deposit;
+pub mod deposit_collateral;
 pub mod initialize_bank;
+pub mod initialize_lending_market;
+pub mod initialize_test_oracle;
 pub mod initialize_user;
 pub mod liquidate;
+pub mod propose_market_owner;
+pub mod refresh_bank;
 pub mod repay;
+pub mod set_bank_flags;
+pub mod set_fee_receiver;
+pub mod set_market_flags;
+pub mod set_market_roles;
+pub mod set_test_oracle_price;
+pub mod update_bank_config;
 pub mod withdraw;
+pub mod withdraw_collateral;
 
+pub use accept_market_owner::*;
 pub use borrow::*;
+pub use clear_bank_flags::*;
+pub use clear_market_flags::*;
+pub use collect_fees::*;
 pub use deposit::*;
+pub use deposit_collateral::*;
 pub use initialize_bank::*;
+pub use initialize_lending_market::*;
+pub use initialize_test_oracle::*;
 pub use initialize_user::*;
 pub use liquidate::*;
+pub use propose_market_owner::*;
+pub use refresh_bank::*;
 pub use repay::*;
+pub use set_bank_flags::*;
+pub use set_fee_receiver::*;
+pub use set_market_flags::*;
+pub use set_market_roles::*;
+pub use set_test_oracle_price::*;
+pub use update_bank_config::*;
 pub use withdraw::*;
+pub use withdraw_collateral::*;
@@ -0,0 +1,24 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, LendingMarket};
+
+#[derive(Accounts)]
+pub struct ProposeMarketOwner<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+}
+
+// Ownership only moves once the new owner accepts, so a mistyped key can't lock the market
+pub fn handler_propose_market_owner(
+    ctx: Context<ProposeMarketOwner>,
+    new_owner: Pubkey,
+) -> Result<()> {
+    ctx.accounts.lending_market.pending_owner = new_owner;
+
+    Ok(())
+}
@@ -0,0 +1,14 @@
+use anchor_lang::prelude::*;
+
+use crate::Bank;
+
+#[derive(Accounts)]
+pub struct RefreshBank<'info> {
+    #[account(mut)]
+    pub bank: Account<'info, Bank>,
+}
+
+pub fn handler_refresh_bank(ctx: Context<RefreshBank>) -> Result<()> {
+    // Anyone can crank the bank forward to the current slot
+    ctx.accounts.bank.refresh(&Clock::get()?)
+}
@@ -119,141 +119,166 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+    token_interface::{Mint, TokenAccount, TokenInterface},
 };
-use std::f32::consts::E;
 
-use crate::{error::ErrorCode, Bank, User};
+use crate::{
+    check_operation,
+    error::ErrorCode,
+    math::{amount_to_shares, shares_to_amount, Rounding},
+    native::{check_native_mint, wrap_native},
+    token::{amount_with_transfer_fee, transfer_tokens},
+    Bank, LendingMarket, Operation, User,
+};
 
 #[derive(Accounts)]
 pub struct Repay<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
+    pub lending_market: Account<'info, LendingMarket>,
+
     pub mint: InterfaceAccount<'info, Mint>,
 
     #[account(
         mut,
-        seeds = [mint.key().as_ref()],
+        seeds = [b"bank", lending_market.key().as_ref(), mint.key().as_ref()],
         bump = bank.bank_bump,
     )]
     pub bank: Account<'info, Bank>,
 
     #[account(
         mut,
-        seeds = [b"treasury", mint.key().as_ref()],
+        seeds = [b"treasury", bank.key().as_ref()],
         bump = bank.treasury_bump
     )]
     pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
 
     #[account(
         mut,
-        seeds = [signer.key().as_ref()],
+        seeds = [
+            b"obligation",
+            lending_market.key().as_ref(),
+            signer.key().as_ref(),
+            &user_account.id.to_le_bytes(),
+        ],
         bump = user_account.bump
     )]
     pub user_account: Account<'info, User>,
 
+    // Not needed when repaying with native SOL
     #[account(
-        init_if_needed,
-        payer = signer,
+        mut,
         associated_token::mint = mint,
         associated_token::authority = signer,
         associated_token::token_program = token_program,
     )]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
 
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
-pub fn handler_repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
+pub fn handler_repay<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Repay<'info>>,
+    amount: u64,
+    use_native: bool,
+) -> Result<()> {
     let bank = &mut ctx.accounts.bank;
     let user = &mut ctx.accounts.user_account;
 
-    // Get the borrow value based on mint
-    let borrow_value = match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => user.borrowed_usdc,
-        _ => user.borrowed_sol,
-    };
+    check_operation(
+        ctx.accounts.lending_market.flags,
+        bank.flags,
+        Operation::Repay,
+    )?;
 
-    // Calculate time difference
-    let time_difference = user.last_updated_borrowed - Clock::get()?.unix_timestamp;
+    if bank.is_stale(&Clock::get()?) {
+        return Err(ErrorCode::BankStale.into());
+    }
 
-    // Update total borrowed with interest
-    bank.total_borrowed = (bank.total_borrowed as f64
-        * E.powf(bank.interest_rate as f32 * time_difference as f32) as f64)
-        as u64;
+    let borrowed_shares = user.borrow_shares(&bank.key());
 
     // Validate there are borrowed shares
     if bank.total_borrowed_shares == 0 {
         return Err(ErrorCode::NoOutstandingBorrows.into());
     }
 
-    // Calculate value per share and user value
-    let value_per_share = bank.total_borrowed as f64 / bank.total_borrowed_shares as f64;
-    let user_value = (borrow_value as f64 / value_per_share) as u64;
+    // Calculate the user's debt including accrued interest
+    let user_value = shares_to_amount(borrowed_shares, bank.borrow_index(), Rounding::Up)?;
 
     // Check for over-repayment
     if amount > user_value {
         return Err(ErrorCode::OverRepay.into());
     }
 
-    // Perform the token transfer
-    let transfer_cpi_accounts = TransferChecked {
-        from: ctx.accounts.user_token_account.to_account_info(),
-        to: ctx.accounts.bank_token_account.to_account_info(),
-        authority: ctx.accounts.signer.to_account_info(),
-        mint: ctx.accounts.mint.to_account_info(),
-    };
-
-    let cpi_program = ctx.accounts.token_program.to_account_info();
-    let cpi_context = CpiContext::new(cpi_program, transfer_cpi_accounts);
-    let decimals = ctx.accounts.mint.decimals;
-    transfer_checked(cpi_context, amount, decimals)?;
+    // The repayment is grossed up by the mint's transfer fee so that `amount` reaches the treasury,
+    // only what actually arrives is credited
+    let balance_before = ctx.accounts.bank_token_account.amount;
+
+    // Perform the token transfer, native SOL is wrapped straight into the treasury
+    if use_native {
+        check_native_mint(&ctx.accounts.mint.key())?;
+
+        wrap_native(
+            ctx.accounts.signer.to_account_info(),
+            ctx.accounts.bank_token_account.to_account_info(),
+            amount,
+            ctx.accounts.system_program.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        )?;
+    } else {
+        let user_token_account = ctx
+            .accounts
+            .user_token_account
+            .as_ref()
+            .ok_or(ErrorCode::MissingUserTokenAccount)?;
+
+        let transfer_amount =
+            amount_with_transfer_fee(&ctx.accounts.mint.to_account_info(), amount)?;
+
+        // Remaining accounts carry the mint's transfer hook accounts
+        transfer_tokens(
+            &ctx.accounts.token_program.to_account_info(),
+            user_token_account.to_account_info(),
+            ctx.accounts.mint.to_account_info(),
+            ctx.accounts.bank_token_account.to_account_info(),
+            ctx.accounts.signer.to_account_info(),
+            ctx.remaining_accounts,
+            transfer_amount,
+            ctx.accounts.mint.decimals,
+            &[],
+        )?;
+    }
 
-    // Calculate shares to repay
-    let borrow_ratio = amount
-        .checked_div(bank.total_borrowed)
-        .ok_or(ErrorCode::MathOverflow)?;
-    let user_shares = bank
-        .total_borrowed_shares
-        .checked_mul(borrow_ratio)
+    ctx.accounts.bank_token_account.reload()?;
+    let received = ctx
+        .accounts
+        .bank_token_account
+        .amount
+        .checked_sub(balance_before)
         .ok_or(ErrorCode::MathOverflow)?;
 
-    // Update user's borrowed amounts and shares
-    match ctx.accounts.mint.to_account_info().key() {
-        key if key == user.usdc_address => {
-            user.borrowed_usdc = user
-                .borrowed_usdc
-                .checked_sub(amount)
-                .ok_or(ErrorCode::MathOverflow)?;
-            user.borrowed_usdc_shares = user
-                .borrowed_usdc_shares
-                .checked_sub(user_shares)
-                .ok_or(ErrorCode::MathOverflow)?;
-        }
-        _ => {
-            user.borrowed_sol = user
-                .borrowed_sol
-                .checked_sub(amount)
-                .ok_or(ErrorCode::MathOverflow)?;
-            user.borrowed_sol_shares = user
-                .borrowed_sol_shares
-                .checked_sub(user_shares)
-                .ok_or(ErrorCode::MathOverflow)?;
-        }
+    let bank = &mut ctx.accounts.bank;
+    let user = &mut ctx.accounts.user_account;
+
+    // Calculate shares to repay
+    let user_shares =
+        amount_to_shares(received, bank.borrow_index(), Rounding::Down)?.min(borrowed_shares);
+    if user_shares == 0 {
+        return Err(ErrorCode::ZeroShares.into());
     }
 
+    // Update user's borrowed shares
+    user.remove_borrow_shares(&bank.key(), user_shares)?;
+
     // Update bank totals
-    bank.total_borrowed = bank
-        .total_borrowed
-        .checked_sub(amount)
-        .ok_or(ErrorCode::MathOverflow)?;
     bank.total_borrowed_shares = bank
         .total_borrowed_shares
         .checked_sub(user_shares)
         .ok_or(ErrorCode::MathOverflow)?;
+    bank.sync_totals()?;
 
     Ok(())
 }
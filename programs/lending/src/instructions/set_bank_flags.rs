@@ -0,0 +1,30 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, Bank, LendingMarket, ALL_FLAGS};
+
+#[derive(Accounts)]
+pub struct SetBankFlags<'info> {
+    pub guardian: Signer<'info>,
+
+    #[account(
+        has_one = guardian @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    #[account(
+        mut,
+        has_one = lending_market,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+// The guardian can only raise flags, clearing them is up to the owner or risk admin
+pub fn handler_set_bank_flags(ctx: Context<SetBankFlags>, flags: u64) -> Result<()> {
+    if flags & !ALL_FLAGS != 0 {
+        return Err(ErrorCode::InvalidFlags.into());
+    }
+
+    ctx.accounts.bank.flags |= flags;
+
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::TokenAccount;
+
+use crate::{error::ErrorCode, Bank, LendingMarket};
+
+#[derive(Accounts)]
+pub struct SetFeeReceiver<'info> {
+    pub fee_admin: Signer<'info>,
+
+    #[account(
+        has_one = fee_admin @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    #[account(
+        mut,
+        has_one = lending_market,
+    )]
+    pub bank: Account<'info, Bank>,
+
+    #[account(
+        token::mint = bank.mint_address,
+    )]
+    pub fee_receiver: InterfaceAccount<'info, TokenAccount>,
+}
+
+pub fn handler_set_fee_receiver(ctx: Context<SetFeeReceiver>) -> Result<()> {
+    ctx.accounts.bank.fee_receiver = ctx.accounts.fee_receiver.key();
+
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, LendingMarket, ALL_FLAGS};
+
+#[derive(Accounts)]
+pub struct SetMarketFlags<'info> {
+    pub guardian: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = guardian @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+}
+
+// The guardian can only raise flags, clearing them is up to the owner or risk admin
+pub fn handler_set_market_flags(ctx: Context<SetMarketFlags>, flags: u64) -> Result<()> {
+    if flags & !ALL_FLAGS != 0 {
+        return Err(ErrorCode::InvalidFlags.into());
+    }
+
+    ctx.accounts.lending_market.flags |= flags;
+
+    Ok(())
+}
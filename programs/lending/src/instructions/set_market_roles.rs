@@ -0,0 +1,29 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, LendingMarket};
+
+#[derive(Accounts)]
+pub struct SetMarketRoles<'info> {
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        has_one = owner @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+}
+
+pub fn handler_set_market_roles(
+    ctx: Context<SetMarketRoles>,
+    risk_admin: Pubkey,
+    guardian: Pubkey,
+    fee_admin: Pubkey,
+) -> Result<()> {
+    let lending_market = &mut ctx.accounts.lending_market;
+
+    lending_market.risk_admin = risk_admin;
+    lending_market.guardian = guardian;
+    lending_market.fee_admin = fee_admin;
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, LendingMarket, TestOracle};
+
+#[derive(Accounts)]
+pub struct SetTestOraclePrice<'info> {
+    pub owner: Signer<'info>,
+
+    // Checked against the market's owner at the time, so the key follows ownership transfers
+    #[account(
+        has_one = owner @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    #[account(
+        mut,
+        has_one = lending_market,
+    )]
+    pub test_oracle: Account<'info, TestOracle>,
+}
+
+// Publishes a new price, stamped with the current time unless `publish_time` is given
+pub fn handler_set_test_oracle_price(
+    ctx: Context<SetTestOraclePrice>,
+    price: i64,
+    exponent: i32,
+    confidence: u64,
+    publish_time: Option<i64>,
+) -> Result<()> {
+    if !cfg!(feature = "test-oracle") {
+        return Err(ErrorCode::TestOracleDisabled.into());
+    }
+
+    let test_oracle = &mut ctx.accounts.test_oracle;
+
+    test_oracle.price = price;
+    test_oracle.exponent = exponent;
+    test_oracle.confidence = confidence;
+    test_oracle.publish_time = match publish_time {
+        Some(publish_time) => publish_time,
+        None => Clock::get()?.unix_timestamp,
+    };
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, Bank, BankConfig, LendingMarket};
+
+#[derive(Accounts)]
+pub struct UpdateBankConfig<'info> {
+    pub risk_admin: Signer<'info>,
+
+    #[account(
+        has_one = risk_admin @ ErrorCode::Unauthorized,
+    )]
+    pub lending_market: Account<'info, LendingMarket>,
+
+    #[account(
+        mut,
+        has_one = lending_market,
+    )]
+    pub bank: Account<'info, Bank>,
+}
+
+pub fn handler_update_bank_config(
+    ctx: Context<UpdateBankConfig>,
+    config: BankConfig,
+) -> Result<()> {
+    config.validate()?;
+
+    // Settle interest at the old rates before the model changes
+    let bank = &mut ctx.accounts.bank;
+    bank.refresh(&Clock::get()?)?;
+    bank.set_config(&config);
+
+    Ok(())
+}
@@ -1,41 +1,47 @@
-use std::f64::consts::E;
-
 use anchor_lang::prelude::*;
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
+    token_interface::{burn, Burn, Mint, TokenAccount, TokenInterface},
 };
 
 use crate::error::ErrorCode;
-use crate::{Bank, User};
+use crate::{
+    check_operation,
+    math::{amount_to_shares, Rounding},
+    native::{check_native_mint, unwrap_native},
+    token::transfer_tokens,
+    Bank, LendingMarket, Operation,
+};
 
 #[derive(Accounts)]
 pub struct Withdraw<'info> {
     #[account(mut)]
     pub signer: Signer<'info>,
 
-    pub mint: InterfaceAccount<'info, Mint>,
+    pub lending_market: Account<'info, LendingMarket>,
+
+    pub mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
         mut,
-        seeds = [mint.key().as_ref()],
+        seeds = [b"bank", lending_market.key().as_ref(), mint.key().as_ref()],
         bump = bank.bank_bump
     )]
-    pub bank: Account<'info, Bank>,
+    pub bank: Box<Account<'info, Bank>>,
 
     #[account(
         mut,
-        seeds = [b"treasury", mint.key().as_ref()],
+        seeds = [b"treasury", bank.key().as_ref()],
         bump = bank.treasury_bump
     )]
-    pub bank_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub bank_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     #[account(
         mut,
-        seeds = [signer.key().as_ref()],
-        bump = user_account.bump
+        seeds = [b"receipt_mint", bank.key().as_ref()],
+        bump = bank.receipt_mint_bump
     )]
-    pub user_account: Account<'info, User>,
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
 
     #[account(
         init_if_needed,
@@ -44,71 +50,94 @@ pub struct Withdraw<'info> {
         associated_token::authority = signer,
         associated_token::token_program = token_program,
     )]
-    pub user_token_account: InterfaceAccount<'info, TokenAccount>,
+    pub user_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        associated_token::mint = receipt_mint,
+        associated_token::authority = signer,
+        associated_token::token_program = token_program,
+    )]
+    pub user_receipt_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
 
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
-pub fn handler_withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
-    let user = &mut ctx.accounts.user_account;
+pub fn handler_withdraw<'info>(
+    ctx: Context<'_, '_, 'info, 'info, Withdraw<'info>>,
+    amount: u64,
+    use_native: bool,
+) -> Result<()> {
     let bank = &mut ctx.accounts.bank;
 
-    // Handles token deposits into the bank, calculating and assigning proportional shares to depositor
-    let deposited_value: u64;
-    if ctx.accounts.mint.to_account_info().key() == user.usdc_address {
-        deposited_value = user.deposited_usdc;
-    } else {
-        deposited_value = user.deposited_sol;
-    }
+    check_operation(
+        ctx.accounts.lending_market.flags,
+        bank.flags,
+        Operation::Withdraw,
+    )?;
 
-    let time_difference = user.last_updated - Clock::get()?.unix_timestamp;
+    if use_native {
+        check_native_mint(&bank.mint_address)?;
+    }
 
-    // Validates withdrawal amount against user's deposit value with accrued interest over time
-    bank.total_deposits = (bank.total_deposits as f64
-        * E.powf(bank.interest_rate as f64 * time_difference as f64))
-        as u64;
+    let clock = Clock::get()?;
+    if bank.is_stale(&clock) {
+        return Err(ErrorCode::BankStale.into());
+    }
 
-    let value_per_share = bank.total_deposits as f64 / bank.total_deposit_shares as f64;
-    let user_value = deposited_value as f64 / value_per_share;
+    // Receipt tokens held outside of a user account are redeemable, collateral has to be
+    // withdrawn from the user account first
+    let shares_to_remove = amount_to_shares(amount, bank.supply_index(), Rounding::Up)?;
 
-    if user_value < amount as f64 {
+    if ctx.accounts.user_receipt_token_account.amount < shares_to_remove {
         return Err(ErrorCode::InsufficientFunds.into());
     }
 
-    // This handles the withdrawal of tokens
-    let transfer_cpi_accounts = TransferChecked {
-        from: ctx.accounts.bank_token_account.to_account_info(),
-        to: ctx.accounts.user_token_account.to_account_info(),
-        authority: ctx.accounts.bank_token_account.to_account_info(),
-        mint: ctx.accounts.mint.to_account_info(),
+    let burn_accounts = Burn {
+        mint: ctx.accounts.receipt_mint.to_account_info(),
+        from: ctx.accounts.user_receipt_token_account.to_account_info(),
+        authority: ctx.accounts.signer.to_account_info(),
     };
 
     let cpi_program = ctx.accounts.token_program.to_account_info();
-    let mint_key = ctx.accounts.mint.key();
-
-    let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", mint_key.as_ref(), &[bank.treasury_bump]]];
-
-    let cpi_context = CpiContext::new_with_signer(cpi_program, transfer_cpi_accounts, signer_seeds);
-    let decimals = ctx.accounts.mint.decimals;
-
-    transfer_checked(cpi_context, amount, decimals)?;
-
-    // Update the user's state and handles the accounting
-    let shares_to_remove =
-        (amount as f64 / bank.total_deposits as f64) * bank.total_deposit_shares as f64;
-
-    if ctx.accounts.mint.to_account_info().key() == user.usdc_address {
-        user.deposited_usdc -= amount;
-        user.deposited_usdc_shares -= shares_to_remove as u64;
-    } else {
-        user.deposited_sol -= amount;
-        user.deposited_sol_shares -= shares_to_remove as u64;
+    let cpi_context = CpiContext::new(cpi_program, burn_accounts);
+    burn(cpi_context, shares_to_remove)?;
+
+    // This handles the withdrawal of tokens, remaining accounts carry the mint's transfer hook
+    // accounts
+    let bank_key = bank.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[b"treasury", bank_key.as_ref(), &[bank.treasury_bump]]];
+
+    transfer_tokens(
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.accounts.mint.to_account_info(),
+        ctx.accounts.user_token_account.to_account_info(),
+        ctx.accounts.bank_token_account.to_account_info(),
+        ctx.remaining_accounts,
+        amount,
+        ctx.accounts.mint.decimals,
+        signer_seeds,
+    )?;
+
+    // Closing the wrapped SOL account pays everything in it out as lamports, including wrapped SOL
+    // the user held before this instruction
+    if use_native {
+        unwrap_native(
+            ctx.accounts.signer.to_account_info(),
+            ctx.accounts.user_token_account.to_account_info(),
+            ctx.accounts.token_program.to_account_info(),
+        )?;
     }
 
-    bank.total_deposits -= amount;
-    bank.total_deposit_shares -= shares_to_remove as u64;
+    // Update the bank's state and handles the accounting
+    bank.total_deposit_shares = bank
+        .total_deposit_shares
+        .checked_sub(shares_to_remove)
+        .ok_or(ErrorCode::MathOverflow)?;
+    bank.sync_totals()?;
 
     Ok(())
 }
@@ -0,0 +1,105 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+
+use crate::{
+    check_operation, error::ErrorCode, health::calculate_health, Bank, LendingMarket, Operation,
+    User,
+};
+
+#[derive(Accounts)]
+pub struct WithdrawCollateral<'info> {
+    pub signer: Signer<'info>,
+
+    pub lending_market: Account<'info, LendingMarket>,
+
+    #[account(
+        has_one = lending_market,
+        has_one = receipt_mint,
+    )]
+    pub bank: Box<Account<'info, Bank>>,
+
+    pub receipt_mint: Box<InterfaceAccount<'info, Mint>>,
+
+    #[account(
+        mut,
+        seeds = [b"collateral_vault", bank.key().as_ref()],
+        bump = bank.collateral_vault_bump
+    )]
+    pub collateral_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        token::mint = receipt_mint,
+        token::authority = signer,
+    )]
+    pub user_receipt_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"obligation",
+            lending_market.key().as_ref(),
+            signer.key().as_ref(),
+            &user_account.id.to_le_bytes(),
+        ],
+        bump = user_account.bump
+    )]
+    pub user_account: Box<Account<'info, User>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+// Releases `amount` deposit shares from the user account back to the signer as receipt tokens
+pub fn handler_withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Result<()> {
+    let user = &mut ctx.accounts.user_account;
+    let bank = &ctx.accounts.bank;
+
+    check_operation(
+        ctx.accounts.lending_market.flags,
+        bank.flags,
+        Operation::Withdraw,
+    )?;
+
+    if user.deposit_shares(&bank.key()) < amount {
+        return Err(ErrorCode::InsufficientFunds.into());
+    }
+
+    user.remove_deposit_shares(&bank.key(), amount)?;
+
+    // Outstanding borrows must stay within the loan-to-value limit of what is left, remaining
+    // accounts hold each bank of the user and its price accounts
+    if !user.borrows.is_empty() {
+        let clock = Clock::get()?;
+        let health = calculate_health(user, ctx.remaining_accounts, &[(bank.key(), bank)], &clock)?;
+
+        if !health.can_borrow() {
+            return Err(ErrorCode::WithdrawalExceedsHealth.into());
+        }
+
+        user.health_factor = health.health_factor()?;
+    }
+
+    let transfer_cpi_accounts = TransferChecked {
+        from: ctx.accounts.collateral_vault.to_account_info(),
+        to: ctx.accounts.user_receipt_token_account.to_account_info(),
+        authority: ctx.accounts.collateral_vault.to_account_info(),
+        mint: ctx.accounts.receipt_mint.to_account_info(),
+    };
+
+    let cpi_program = ctx.accounts.token_program.to_account_info();
+    let bank_key = bank.key();
+    let signer_seeds: &[&[&[u8]]] = &[&[
+        b"collateral_vault",
+        bank_key.as_ref(),
+        &[bank.collateral_vault_bump],
+    ]];
+
+    let cpi_context = CpiContext::new_with_signer(cpi_program, transfer_cpi_accounts, signer_seeds);
+    let decimals = ctx.accounts.receipt_mint.decimals;
+
+    transfer_checked(cpi_context, amount, decimals)?;
+
+    Ok(())
+}
@@ -1,7 +1,12 @@
 pub mod constants;
 pub mod error;
+pub mod health;
 pub mod instructions;
+pub mod math;
+pub mod native;
+pub mod oracle;
 pub mod state;
+pub mod token;
 
 use anchor_lang::prelude::*;
 
@@ -15,35 +20,146 @@ declare_id!("6E3EJE7gcpDnhvmx2qFo5SXDNnAFCwyFew8JoTarNXas");
 pub mod lending {
     use super::*;
 
-    pub fn initialize_bank(
-        ctx: Context<InitializeBank>,
-        liquidation_threshold: u64,
-        max_ltw: u64,
+    pub fn initialize_lending_market(
+        ctx: Context<InitializeLendingMarket>,
+        quote_currency: [u8; 32],
     ) -> Result<()> {
-        initialize_bank::handler_initialize_bank(ctx, liquidation_threshold, max_ltw)
+        initialize_lending_market::handler_initialize_lending_market(ctx, quote_currency)
     }
 
-    pub fn initialize_user(ctx: Context<InitializeUser>, usdc_address: Pubkey) -> Result<()> {
-        initialize_user::handler_initialize_user(ctx, usdc_address)
+    pub fn propose_market_owner(ctx: Context<ProposeMarketOwner>, new_owner: Pubkey) -> Result<()> {
+        propose_market_owner::handler_propose_market_owner(ctx, new_owner)
     }
 
-    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
-        deposit::handler_deposit(ctx, amount)
+    pub fn accept_market_owner(ctx: Context<AcceptMarketOwner>) -> Result<()> {
+        accept_market_owner::handler_accept_market_owner(ctx)
     }
 
-    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
-        withdraw::handler_withdraw(ctx, amount)
+    pub fn set_market_roles(
+        ctx: Context<SetMarketRoles>,
+        risk_admin: Pubkey,
+        guardian: Pubkey,
+        fee_admin: Pubkey,
+    ) -> Result<()> {
+        set_market_roles::handler_set_market_roles(ctx, risk_admin, guardian, fee_admin)
+    }
+
+    pub fn set_market_flags(ctx: Context<SetMarketFlags>, flags: u64) -> Result<()> {
+        set_market_flags::handler_set_market_flags(ctx, flags)
+    }
+
+    pub fn clear_market_flags(ctx: Context<ClearMarketFlags>, flags: u64) -> Result<()> {
+        clear_market_flags::handler_clear_market_flags(ctx, flags)
+    }
+
+    pub fn initialize_bank<'info>(
+        ctx: Context<'_, '_, 'info, 'info, InitializeBank<'info>>,
+        config: BankConfig,
+        seed_amount: u64,
+    ) -> Result<()> {
+        initialize_bank::handler_initialize_bank(ctx, config, seed_amount)
+    }
+
+    pub fn update_bank_config(ctx: Context<UpdateBankConfig>, config: BankConfig) -> Result<()> {
+        update_bank_config::handler_update_bank_config(ctx, config)
+    }
+
+    pub fn set_bank_flags(ctx: Context<SetBankFlags>, flags: u64) -> Result<()> {
+        set_bank_flags::handler_set_bank_flags(ctx, flags)
+    }
+
+    pub fn clear_bank_flags(ctx: Context<ClearBankFlags>, flags: u64) -> Result<()> {
+        clear_bank_flags::handler_clear_bank_flags(ctx, flags)
+    }
+
+    pub fn set_fee_receiver(ctx: Context<SetFeeReceiver>) -> Result<()> {
+        set_fee_receiver::handler_set_fee_receiver(ctx)
+    }
+
+    pub fn collect_fees<'info>(
+        ctx: Context<'_, '_, 'info, 'info, CollectFees<'info>>,
+    ) -> Result<()> {
+        collect_fees::handler_collect_fees(ctx)
+    }
+
+    pub fn initialize_test_oracle(
+        ctx: Context<InitializeTestOracle>,
+        price: i64,
+        exponent: i32,
+        confidence: u64,
+    ) -> Result<()> {
+        initialize_test_oracle::handler_initialize_test_oracle(ctx, price, exponent, confidence)
+    }
+
+    pub fn set_test_oracle_price(
+        ctx: Context<SetTestOraclePrice>,
+        price: i64,
+        exponent: i32,
+        confidence: u64,
+        publish_time: Option<i64>,
+    ) -> Result<()> {
+        set_test_oracle_price::handler_set_test_oracle_price(
+            ctx,
+            price,
+            exponent,
+            confidence,
+            publish_time,
+        )
     }
 
-    pub fn borrow(ctx: Context<Borrow>, amount: u64) -> Result<()> {
-        borrow::handler_borrow(ctx, amount)
+    pub fn initialize_user(ctx: Context<InitializeUser>, id: u64) -> Result<()> {
+        initialize_user::handler_initialize_user(ctx, id)
     }
 
-    pub fn repay(ctx: Context<Repay>, amount: u64) -> Result<()> {
-        repay::handler_repay(ctx, amount)
+    pub fn refresh_bank(ctx: Context<RefreshBank>) -> Result<()> {
+        refresh_bank::handler_refresh_bank(ctx)
     }
 
-    pub fn liquidate(ctx: Context<Liquidate>) -> Result<()> {
-        liquidate::handler_liquidate(ctx)
+    pub fn deposit<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Deposit<'info>>,
+        amount: u64,
+        use_native: bool,
+    ) -> Result<()> {
+        deposit::handler_deposit(ctx, amount, use_native)
+    }
+
+    pub fn withdraw<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Withdraw<'info>>,
+        amount: u64,
+        use_native: bool,
+    ) -> Result<()> {
+        withdraw::handler_withdraw(ctx, amount, use_native)
+    }
+
+    pub fn deposit_collateral(ctx: Context<DepositCollateral>, amount: u64) -> Result<()> {
+        deposit_collateral::handler_deposit_collateral(ctx, amount)
+    }
+
+    pub fn withdraw_collateral(ctx: Context<WithdrawCollateral>, amount: u64) -> Result<()> {
+        withdraw_collateral::handler_withdraw_collateral(ctx, amount)
+    }
+
+    pub fn borrow<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Borrow<'info>>,
+        amount: u64,
+        use_native: bool,
+    ) -> Result<()> {
+        borrow::handler_borrow(ctx, amount, use_native)
+    }
+
+    pub fn repay<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Repay<'info>>,
+        amount: u64,
+        use_native: bool,
+    ) -> Result<()> {
+        repay::handler_repay(ctx, amount, use_native)
+    }
+
+    pub fn liquidate<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Liquidate<'info>>,
+        repay_amount: u64,
+        min_collateral_out: u64,
+    ) -> Result<()> {
+        liquidate::handler_liquidate(ctx, repay_amount, min_collateral_out)
     }
 }
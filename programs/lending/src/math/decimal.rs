@@ -0,0 +1,183 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+
+// Kept in its own module so the macro doesn't pick up anchor's `Result` alias
+#[allow(clippy::assign_op_pattern, clippy::manual_div_ceil)]
+mod uint_types {
+    use uint::construct_uint;
+
+    construct_uint! {
+        pub struct U192(3);
+    }
+}
+
+pub use uint_types::U192;
+
+// Fixed-point scale: 18 decimal places
+pub const WAD: u64 = 1_000_000_000_000_000_000;
+pub const BPS_SCALE: u64 = 10_000;
+
+// Unsigned WAD-scaled fixed-point number backed by a 192-bit integer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Decimal(pub U192);
+
+impl Decimal {
+    pub fn zero() -> Self {
+        Self(U192::zero())
+    }
+
+    pub fn one() -> Self {
+        Self(Self::wad())
+    }
+
+    fn wad() -> U192 {
+        U192::from(WAD)
+    }
+
+    pub fn from_scaled_val(scaled_val: u128) -> Self {
+        Self(U192::from(scaled_val))
+    }
+
+    pub fn to_scaled_val(&self) -> Result<u128> {
+        u128::try_from(self.0).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+
+    pub fn from_bps(bps: u64) -> Self {
+        Self(U192::from(bps) * Self::wad() / U192::from(BPS_SCALE))
+    }
+
+    pub fn try_add(self, rhs: Self) -> Result<Self> {
+        Ok(Self(
+            self.0.checked_add(rhs.0).ok_or(ErrorCode::MathOverflow)?,
+        ))
+    }
+
+    pub fn try_sub(self, rhs: Self) -> Result<Self> {
+        Ok(Self(
+            self.0.checked_sub(rhs.0).ok_or(ErrorCode::MathOverflow)?,
+        ))
+    }
+
+    pub fn try_mul(self, rhs: Self) -> Result<Self> {
+        Ok(Self(
+            self.0
+                .checked_mul(rhs.0)
+                .ok_or(ErrorCode::MathOverflow)?
+                .checked_div(Self::wad())
+                .ok_or(ErrorCode::MathOverflow)?,
+        ))
+    }
+
+    pub fn try_div(self, rhs: Self) -> Result<Self> {
+        Ok(Self(
+            self.0
+                .checked_mul(Self::wad())
+                .ok_or(ErrorCode::MathOverflow)?
+                .checked_div(rhs.0)
+                .ok_or(ErrorCode::MathOverflow)?,
+        ))
+    }
+
+    // Exponentiation by squaring
+    pub fn try_pow(self, mut exp: u64) -> Result<Self> {
+        let mut base = self;
+        let mut result = Self::one();
+
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.try_mul(base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.try_mul(base)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub fn try_floor_u64(&self) -> Result<u64> {
+        let value = self
+            .0
+            .checked_div(Self::wad())
+            .ok_or(ErrorCode::MathOverflow)?;
+        u64::try_from(value).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+
+    pub fn try_ceil_u64(&self) -> Result<u64> {
+        let value = self
+            .0
+            .checked_add(Self::wad() - U192::one())
+            .ok_or(ErrorCode::MathOverflow)?
+            .checked_div(Self::wad())
+            .ok_or(ErrorCode::MathOverflow)?;
+        u64::try_from(value).map_err(|_| ErrorCode::MathOverflow.into())
+    }
+}
+
+impl From<u64> for Decimal {
+    fn from(value: u64) -> Self {
+        Self(U192::from(value) * Self::wad())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ratio(numerator: u64, denominator: u64) -> Decimal {
+        Decimal::from(numerator)
+            .try_div(Decimal::from(denominator))
+            .unwrap()
+    }
+
+    #[test]
+    fn mul_and_div() {
+        assert_eq!(ratio(3, 2).try_mul(ratio(5, 2)).unwrap(), ratio(15, 4));
+        assert_eq!(
+            Decimal::from(10).try_div(Decimal::from(4)).unwrap(),
+            ratio(5, 2)
+        );
+        assert_eq!(Decimal::from_bps(2_500), ratio(1, 4));
+        assert_eq!(
+            ratio(1, 3).0,
+            U192::from(333_333_333_333_333_333u64),
+            "division truncates"
+        );
+    }
+
+    #[test]
+    fn pow() {
+        assert_eq!(Decimal::from(2).try_pow(10).unwrap(), Decimal::from(1_024));
+        assert_eq!(ratio(11, 10).try_pow(2).unwrap(), ratio(121, 100));
+        assert_eq!(ratio(7, 3).try_pow(0).unwrap(), Decimal::one());
+    }
+
+    #[test]
+    fn floor_and_ceil() {
+        assert_eq!(ratio(3, 2).try_floor_u64().unwrap(), 1);
+        assert_eq!(ratio(3, 2).try_ceil_u64().unwrap(), 2);
+        assert_eq!(Decimal::from(2).try_floor_u64().unwrap(), 2);
+        assert_eq!(Decimal::from(2).try_ceil_u64().unwrap(), 2);
+
+        let smallest = Decimal::from_scaled_val(1);
+        assert_eq!(smallest.try_floor_u64().unwrap(), 0);
+        assert_eq!(smallest.try_ceil_u64().unwrap(), 1);
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let max = Decimal::from(u64::MAX);
+
+        assert!(max.try_mul(max).is_err());
+        assert!(max
+            .try_add(Decimal::one())
+            .unwrap()
+            .try_floor_u64()
+            .is_err());
+        assert!(Decimal::one().try_div(Decimal::zero()).is_err());
+        assert!(Decimal::zero().try_sub(Decimal::one()).is_err());
+        assert!(Decimal::from(2).try_pow(200).is_err());
+    }
+}
@@ -0,0 +1,46 @@
+use anchor_lang::prelude::*;
+
+use crate::{math::Decimal, SECONDS_PER_YEAR};
+
+// Growth factor of a balance compounding every second at `annual_rate` for `elapsed` seconds:
+// (1 + annual_rate / SECONDS_PER_YEAR) ^ elapsed
+pub fn compounded_interest_factor(annual_rate: Decimal, elapsed: u64) -> Result<Decimal> {
+    let rate_per_second = annual_rate.try_div(Decimal::from(SECONDS_PER_YEAR))?;
+
+    Decimal::one().try_add(rate_per_second)?.try_pow(elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Decimal, expected_scaled: u128, tolerance: u128) {
+        let actual = actual.to_scaled_val().unwrap();
+        assert!(
+            actual.abs_diff(expected_scaled) <= tolerance,
+            "{actual} is not within {tolerance} of {expected_scaled}"
+        );
+    }
+
+    #[test]
+    fn compounds_to_exp_over_a_year() {
+        // 5% APR compounded every second for a year is e^0.05 = 1.051271096376024...
+        let factor = compounded_interest_factor(Decimal::from_bps(500), SECONDS_PER_YEAR).unwrap();
+        assert_close(factor, 1_051_271_096_376_024_040, 1_000_000_000);
+    }
+
+    #[test]
+    fn compounds_per_second() {
+        // One second at 100% APR grows by exactly one second's worth of interest
+        let factor = compounded_interest_factor(Decimal::one(), 1).unwrap();
+        let rate_per_second = Decimal::one()
+            .try_div(Decimal::from(SECONDS_PER_YEAR))
+            .unwrap();
+        assert_eq!(factor, Decimal::one().try_add(rate_per_second).unwrap());
+
+        assert_eq!(
+            compounded_interest_factor(Decimal::from_bps(500), 0).unwrap(),
+            Decimal::one()
+        );
+    }
+}
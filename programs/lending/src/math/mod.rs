@@ -0,0 +1,7 @@
+pub mod decimal;
+pub mod interest;
+pub mod shares;
+
+pub use decimal::*;
+pub use interest::*;
+pub use shares::*;
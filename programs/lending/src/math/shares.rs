@@ -0,0 +1,98 @@
+use anchor_lang::prelude::*;
+
+use super::Decimal;
+
+// Direction to round conversions in, always picked so that the protocol never loses the remainder
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+impl Rounding {
+    fn apply(self, value: Decimal) -> Result<u64> {
+        match self {
+            Rounding::Down => value.try_floor_u64(),
+            Rounding::Up => value.try_ceil_u64(),
+        }
+    }
+}
+
+// Shares worth `amount` tokens at `index` tokens per share
+pub fn amount_to_shares(amount: u64, index: Decimal, rounding: Rounding) -> Result<u64> {
+    rounding.apply(Decimal::from(amount).try_div(index)?)
+}
+
+// Tokens `shares` are worth at `index` tokens per share
+pub fn shares_to_amount(shares: u64, index: Decimal, rounding: Rounding) -> Result<u64> {
+    rounding.apply(Decimal::from(shares).try_mul(index)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(numerator: u64, denominator: u64) -> Decimal {
+        Decimal::from(numerator)
+            .try_div(Decimal::from(denominator))
+            .unwrap()
+    }
+
+    #[test]
+    fn rounds_in_the_requested_direction() {
+        let index = index(3, 2);
+
+        assert_eq!(amount_to_shares(10, index, Rounding::Down).unwrap(), 6);
+        assert_eq!(amount_to_shares(10, index, Rounding::Up).unwrap(), 7);
+        assert_eq!(shares_to_amount(7, index, Rounding::Down).unwrap(), 10);
+        assert_eq!(shares_to_amount(7, index, Rounding::Up).unwrap(), 11);
+
+        // Exact conversions don't round either way
+        assert_eq!(amount_to_shares(9, index, Rounding::Up).unwrap(), 6);
+        assert_eq!(shares_to_amount(6, index, Rounding::Up).unwrap(), 9);
+    }
+
+    #[test]
+    fn round_trips_never_favor_the_user() {
+        for index in [index(1, 1), index(11, 10), index(3, 2), index(7, 3)] {
+            for amount in [1, 2, 3, 999, 1_000_001, u32::MAX as u64] {
+                // Deposits get shares rounded down and redeem them rounded down
+                let shares = amount_to_shares(amount, index, Rounding::Down).unwrap();
+                assert!(shares_to_amount(shares, index, Rounding::Down).unwrap() <= amount);
+
+                // Borrows owe shares rounded up and repay them rounded up
+                let shares = amount_to_shares(amount, index, Rounding::Up).unwrap();
+                assert!(shares_to_amount(shares, index, Rounding::Up).unwrap() >= amount);
+
+                // Withdrawals burn shares rounded up for the amount they pay out
+                let shares = amount_to_shares(amount, index, Rounding::Up).unwrap();
+                assert!(shares_to_amount(shares, index, Rounding::Down).unwrap() >= amount);
+            }
+        }
+    }
+
+    #[test]
+    fn second_deposit_smaller_than_the_pool_gets_its_shares() {
+        // The old ratio `amount / total_deposits` truncated to 0 for any deposit smaller than
+        // the pool and credited no shares at all
+        let index = index(6, 5);
+        let first = amount_to_shares(1_000_000, index, Rounding::Down).unwrap();
+        let second = amount_to_shares(500, index, Rounding::Down).unwrap();
+        assert_eq!(first, 833_333);
+        assert_eq!(second, 416);
+
+        // Both deposits are still redeemable for what they put in, less rounding
+        assert_eq!(
+            shares_to_amount(first, index, Rounding::Down).unwrap(),
+            999_999
+        );
+        assert_eq!(
+            shares_to_amount(second, index, Rounding::Down).unwrap(),
+            499
+        );
+        assert_eq!(
+            shares_to_amount(first + second, index, Rounding::Down).unwrap(),
+            1_000_498
+        );
+    }
+}
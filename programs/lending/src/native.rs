@@ -0,0 +1,56 @@
+use anchor_lang::prelude::*;
+use anchor_lang::system_program::{transfer, Transfer};
+use anchor_spl::token::spl_token;
+use anchor_spl::token_interface::{
+    close_account, spl_token_2022, sync_native, CloseAccount, SyncNative,
+};
+
+use crate::error::ErrorCode;
+
+pub fn is_native_mint(mint: &Pubkey) -> bool {
+    *mint == spl_token::native_mint::ID || *mint == spl_token_2022::native_mint::ID
+}
+
+pub fn check_native_mint(mint: &Pubkey) -> Result<()> {
+    if !is_native_mint(mint) {
+        return Err(ErrorCode::NotNativeMint.into());
+    }
+
+    Ok(())
+}
+
+// Moves lamports from the signer straight into a wrapped SOL account and syncs its token balance
+pub fn wrap_native<'info>(
+    signer: AccountInfo<'info>,
+    token_account: AccountInfo<'info>,
+    amount: u64,
+    system_program: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+) -> Result<()> {
+    let transfer_accounts = Transfer {
+        from: signer,
+        to: token_account.clone(),
+    };
+    transfer(CpiContext::new(system_program, transfer_accounts), amount)?;
+
+    let sync_accounts = SyncNative {
+        account: token_account,
+    };
+    sync_native(CpiContext::new(token_program, sync_accounts))
+}
+
+// Closes the signer's wrapped SOL account, paying out its whole balance and rent as lamports. Any
+// wrapped SOL the signer already held in the account is unwrapped along with the transfer, clients
+// that want to keep it should use a non-native withdraw or borrow instead.
+pub fn unwrap_native<'info>(
+    signer: AccountInfo<'info>,
+    token_account: AccountInfo<'info>,
+    token_program: AccountInfo<'info>,
+) -> Result<()> {
+    let close_accounts = CloseAccount {
+        account: token_account,
+        destination: signer.clone(),
+        authority: signer,
+    };
+    close_account(CpiContext::new(token_program, close_accounts))
+}
@@ -0,0 +1,462 @@
+use anchor_lang::prelude::*;
+use pyth_solana_receiver_sdk::price_update::{PriceUpdateV2, VerificationLevel};
+use switchboard_on_demand::{PullFeedAccountData, SWITCHBOARD_ON_DEMAND_PROGRAM_ID};
+
+use crate::{
+    error::ErrorCode, health::load_account, math::Decimal, Bank, OracleConfig, OracleSource,
+    TestOracle,
+};
+
+// USD price of one whole token along with the feed's confidence interval
+#[derive(Clone, Copy, Debug)]
+pub struct OraclePrice {
+    pub price: Decimal,
+    pub confidence: Decimal,
+    pub use_confidence_interval: bool,
+}
+
+impl OraclePrice {
+    // Lower bound of the confidence interval when enabled, used for deposits
+    pub fn collateral_price(&self) -> Decimal {
+        if !self.use_confidence_interval {
+            return self.price;
+        }
+
+        self.price
+            .try_sub(self.confidence)
+            .unwrap_or(Decimal::zero())
+    }
+
+    // Upper bound of the confidence interval when enabled, used for borrows
+    pub fn debt_price(&self) -> Result<Decimal> {
+        if !self.use_confidence_interval {
+            return Ok(self.price);
+        }
+
+        self.price.try_add(self.confidence)
+    }
+}
+
+// Reads the bank's primary oracle, and its fallback when one is configured, from the next
+// accounts of `accounts`. The primary price is used when both are available as long as the two
+// don't diverge by more than the bank allows, otherwise whichever one is available. Only a stale,
+// too uncertain or invalid price lets the other source take over, a wrong account fails the call.
+pub fn get_bank_price<'a, 'info: 'a>(
+    bank: &Bank,
+    accounts: &mut impl Iterator<Item = &'a AccountInfo<'info>>,
+    clock: &Clock,
+) -> Result<OraclePrice> {
+    let primary_info = accounts.next().ok_or(ErrorCode::InvalidHealthAccounts)?;
+    let primary = get_price(&bank.oracle, primary_info, clock);
+
+    let Some(fallback_oracle) = &bank.fallback_oracle else {
+        return primary;
+    };
+    let primary = usable_or_fail(primary)?;
+
+    let fallback_info = accounts.next().ok_or(ErrorCode::InvalidHealthAccounts)?;
+    let fallback = usable_or_fail(get_price(fallback_oracle, fallback_info, clock))?;
+
+    match (primary, fallback) {
+        (Ok(primary), Ok(fallback)) => {
+            let divergence = if primary.price > fallback.price {
+                primary.price.try_sub(fallback.price)?
+            } else {
+                fallback.price.try_sub(primary.price)?
+            };
+            let max_divergence = fallback
+                .price
+                .try_mul(Decimal::from_bps(bank.max_price_divergence))?;
+
+            if divergence > max_divergence {
+                return Err(ErrorCode::PriceDivergence.into());
+            }
+
+            Ok(primary)
+        }
+        (Ok(primary), Err(_)) => Ok(primary),
+        (Err(_), Ok(fallback)) => Ok(fallback),
+        (Err(error), Err(_)) => Err(error),
+    }
+}
+
+// Reads a single oracle from `price_info` and normalises it to a USD price per whole token
+pub fn get_price(
+    config: &OracleConfig,
+    price_info: &AccountInfo,
+    clock: &Clock,
+) -> Result<OraclePrice> {
+    if config.price_account != Pubkey::default() && price_info.key() != config.price_account {
+        return Err(ErrorCode::InvalidOracleAccount.into());
+    }
+
+    let (price, confidence) = match config.source {
+        OracleSource::PythPull => {
+            let price_update = load_account::<PriceUpdateV2>(price_info)?;
+            if !price_update.verification_level.gte(VerificationLevel::Full) {
+                return Err(ErrorCode::InvalidOracleAccount.into());
+            }
+
+            let price = price_update
+                .get_price_unchecked(&config.feed_id)
+                .map_err(|_| ErrorCode::InvalidOracleAccount)?;
+            check_price_age(price.publish_time, config.max_age, clock)?;
+
+            if price.price <= 0 {
+                return Err(ErrorCode::InvalidPrice.into());
+            }
+
+            (
+                scale_by_exponent(price.price as u64, price.exponent)?,
+                scale_by_exponent(price.conf, price.exponent)?,
+            )
+        }
+        OracleSource::SwitchboardOnDemand => {
+            if *price_info.owner != SWITCHBOARD_ON_DEMAND_PROGRAM_ID {
+                return Err(ErrorCode::InvalidOracleAccount.into());
+            }
+
+            let feed = PullFeedAccountData::parse(price_info.try_borrow_data()?)
+                .map_err(|_| ErrorCode::InvalidOracleAccount)?;
+
+            if feed.result.slot == 0 {
+                return Err(ErrorCode::InvalidPrice.into());
+            }
+            check_price_age(feed.result_ts(), config.max_age, clock)?;
+
+            // Switchboard results already carry 18 decimals
+            let price = u128::try_from(feed.result.value)
+                .ok()
+                .filter(|price| *price > 0)
+                .ok_or(ErrorCode::InvalidPrice)?;
+            let confidence = u128::try_from(feed.result.std_dev).unwrap_or(0);
+
+            (
+                Decimal::from_scaled_val(price),
+                Decimal::from_scaled_val(confidence),
+            )
+        }
+        OracleSource::FixedPrice => (
+            scale_by_exponent(config.fixed_price, config.fixed_price_exponent)?,
+            Decimal::zero(),
+        ),
+        OracleSource::TestOracle => {
+            if !cfg!(feature = "test-oracle") {
+                return Err(ErrorCode::TestOracleDisabled.into());
+            }
+
+            let test_oracle = load_account::<TestOracle>(price_info)?;
+            check_price_age(test_oracle.publish_time, config.max_age, clock)?;
+
+            if test_oracle.price <= 0 {
+                return Err(ErrorCode::InvalidPrice.into());
+            }
+
+            (
+                scale_by_exponent(test_oracle.price as u64, test_oracle.exponent)?,
+                scale_by_exponent(test_oracle.confidence, test_oracle.exponent)?,
+            )
+        }
+    };
+
+    // Reject prices the publishers are too unsure about
+    let max_confidence = price.try_mul(Decimal::from_bps(config.max_confidence))?;
+    if confidence > max_confidence {
+        return Err(ErrorCode::PriceConfidenceTooWide.into());
+    }
+
+    Ok(OraclePrice {
+        price,
+        confidence,
+        use_confidence_interval: config.use_confidence_interval,
+    })
+}
+
+// USD value of `amount` base units of a token with `decimals` decimals. Converting to whole tokens
+// first keeps the intermediate product small enough for any u64 amount at realistic prices.
+pub fn token_value(amount: Decimal, price: Decimal, decimals: u8) -> Result<Decimal> {
+    amount.try_div(ten_pow(decimals as u32)?)?.try_mul(price)
+}
+
+// Amount of base units of a token with `decimals` decimals worth `value` USD
+pub fn token_amount(value: Decimal, price: Decimal, decimals: u8) -> Result<Decimal> {
+    value.try_mul(ten_pow(decimals as u32)?)?.try_div(price)
+}
+
+// Keeps errors of a correctly identified oracle that only make its current price unusable, so the
+// other source can take over, and fails on anything else
+fn usable_or_fail(price: Result<OraclePrice>) -> Result<Result<OraclePrice>> {
+    match price {
+        Err(error)
+            if ![
+                ErrorCode::StalePrice,
+                ErrorCode::InvalidPrice,
+                ErrorCode::PriceConfidenceTooWide,
+            ]
+            .into_iter()
+            .any(|code| error == code.into()) =>
+        {
+            Err(error)
+        }
+        price => Ok(price),
+    }
+}
+
+fn check_price_age(publish_time: i64, max_age: u64, clock: &Clock) -> Result<()> {
+    let age = clock.unix_timestamp.saturating_sub(publish_time);
+    if age > max_age as i64 {
+        return Err(ErrorCode::StalePrice.into());
+    }
+
+    Ok(())
+}
+
+fn scale_by_exponent(value: u64, exponent: i32) -> Result<Decimal> {
+    let scale = ten_pow(exponent.unsigned_abs())?;
+
+    if exponent < 0 {
+        Decimal::from(value).try_div(scale)
+    } else {
+        Decimal::from(value).try_mul(scale)
+    }
+}
+
+fn ten_pow(exponent: u32) -> Result<Decimal> {
+    let value = 10u64.checked_pow(exponent).ok_or(ErrorCode::MathOverflow)?;
+
+    Ok(Decimal::from(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::WAD;
+    use pyth_solana_receiver_sdk::price_update::PriceFeedMessage;
+    use switchboard_on_demand::{CurrentResult, Discriminator as _};
+
+    const NOW: i64 = 1_000;
+    const FEED_ID: [u8; 32] = [7; 32];
+
+    struct OracleAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+        start: usize,
+    }
+
+    impl OracleAccount {
+        fn new(owner: Pubkey, data: &[u8]) -> Self {
+            // Zero-copy feeds need their fields aligned past the 8 byte discriminator
+            let mut buffer = vec![0u8; data.len() + 16];
+            let start = (16 - (buffer.as_ptr() as usize + 8) % 16) % 16;
+            buffer[start..start + data.len()].copy_from_slice(data);
+
+            Self {
+                key: Pubkey::new_unique(),
+                owner,
+                lamports: 0,
+                data: buffer,
+                start,
+            }
+        }
+
+        fn info(&mut self) -> AccountInfo<'_> {
+            AccountInfo::new(
+                &self.key,
+                false,
+                false,
+                &mut self.lamports,
+                &mut self.data[self.start..],
+                &self.owner,
+                false,
+                0,
+            )
+        }
+    }
+
+    fn clock() -> Clock {
+        Clock {
+            unix_timestamp: NOW,
+            ..Clock::default()
+        }
+    }
+
+    // A Pyth price with 8 decimals
+    fn pyth_account(price: i64, conf: u64, publish_time: i64) -> OracleAccount {
+        let update = PriceUpdateV2 {
+            write_authority: Pubkey::default(),
+            verification_level: VerificationLevel::Full,
+            price_message: PriceFeedMessage {
+                feed_id: FEED_ID,
+                price,
+                conf,
+                exponent: -8,
+                publish_time,
+                prev_publish_time: publish_time,
+                ema_price: price,
+                ema_conf: conf,
+            },
+            posted_slot: 0,
+        };
+        let mut data = Vec::new();
+        update.try_serialize(&mut data).unwrap();
+
+        OracleAccount::new(PriceUpdateV2::owner(), &data)
+    }
+
+    fn switchboard_account(value: i128, std_dev: i128, timestamp: i64) -> OracleAccount {
+        let size = std::mem::size_of::<PullFeedAccountData>();
+        let mut data = vec![0u8; 8 + size];
+        data[..8].copy_from_slice(&PullFeedAccountData::discriminator());
+
+        let result = 8 + std::mem::offset_of!(PullFeedAccountData, result);
+        data[result..result + 16].copy_from_slice(&value.to_le_bytes());
+        data[result + 16..result + 32].copy_from_slice(&std_dev.to_le_bytes());
+        let slot = result + std::mem::offset_of!(CurrentResult, slot);
+        data[slot..slot + 8].copy_from_slice(&1u64.to_le_bytes());
+        let timestamps = 8 + std::mem::offset_of!(PullFeedAccountData, submission_timestamps);
+        data[timestamps..timestamps + 8].copy_from_slice(&timestamp.to_le_bytes());
+
+        OracleAccount::new(SWITCHBOARD_ON_DEMAND_PROGRAM_ID, &data)
+    }
+
+    fn oracle_config(source: OracleSource, price_account: Pubkey) -> OracleConfig {
+        OracleConfig {
+            source,
+            feed_id: FEED_ID,
+            price_account,
+            max_age: 60,
+            max_confidence: 200,
+            use_confidence_interval: false,
+            fixed_price: 0,
+            fixed_price_exponent: 0,
+        }
+    }
+
+    fn fixed_config(price: u64) -> OracleConfig {
+        OracleConfig {
+            fixed_price: price,
+            ..oracle_config(OracleSource::FixedPrice, Pubkey::default())
+        }
+    }
+
+    // A Pyth primary configured for `price_account`, backed by a fixed price fallback
+    fn price_with_fallback(
+        primary: &mut OracleAccount,
+        price_account: Option<Pubkey>,
+        fallback_price: u64,
+        max_price_divergence: u64,
+    ) -> Result<OraclePrice> {
+        let primary_key = price_account.unwrap_or(primary.key);
+        let data = [0u8; Bank::INIT_SPACE];
+        let mut bank = Bank::deserialize(&mut &data[..]).unwrap();
+        bank.oracle = oracle_config(OracleSource::PythPull, primary_key);
+        bank.fallback_oracle = Some(fixed_config(fallback_price));
+        bank.max_price_divergence = max_price_divergence;
+
+        let mut fallback = OracleAccount::new(Pubkey::default(), &[]);
+        let accounts = [primary.info(), fallback.info()];
+        get_bank_price(&bank, &mut accounts.iter(), &clock())
+    }
+
+    #[test]
+    fn only_unusable_prices_let_the_other_source_take_over() {
+        for code in [
+            ErrorCode::StalePrice,
+            ErrorCode::InvalidPrice,
+            ErrorCode::PriceConfidenceTooWide,
+        ] {
+            assert!(usable_or_fail(Err(code.into())).unwrap().is_err());
+        }
+
+        for code in [
+            ErrorCode::InvalidOracleAccount,
+            ErrorCode::InvalidHealthAccounts,
+            ErrorCode::MathOverflow,
+        ] {
+            assert_eq!(usable_or_fail(Err(code.into())).unwrap_err(), code.into());
+        }
+    }
+
+    #[test]
+    fn falls_back_when_the_primary_is_stale_or_uncertain() {
+        let mut stale = pyth_account(100_0000_0000, 0, NOW - 61);
+        let price = price_with_fallback(&mut stale, None, 101, 500).unwrap();
+        assert_eq!(price.price, Decimal::from(101));
+
+        // A 3% confidence interval is wider than the 2% allowed
+        let mut uncertain = pyth_account(100_0000_0000, 3_0000_0000, NOW);
+        let price = price_with_fallback(&mut uncertain, None, 101, 500).unwrap();
+        assert_eq!(price.price, Decimal::from(101));
+    }
+
+    #[test]
+    fn prefers_the_primary_unless_the_sources_diverge() {
+        let mut primary = pyth_account(100_0000_0000, 0, NOW);
+        let price = price_with_fallback(&mut primary, None, 104, 500).unwrap();
+        assert_eq!(price.price, Decimal::from(100));
+
+        assert_eq!(
+            price_with_fallback(&mut primary, None, 106, 500).unwrap_err(),
+            ErrorCode::PriceDivergence.into()
+        );
+    }
+
+    #[test]
+    fn a_wrong_primary_account_fails_instead_of_falling_back() {
+        let mut primary = pyth_account(100_0000_0000, 0, NOW);
+        assert_eq!(
+            price_with_fallback(&mut primary, Some(Pubkey::new_unique()), 100, 500).unwrap_err(),
+            ErrorCode::InvalidOracleAccount.into()
+        );
+    }
+
+    #[test]
+    fn parses_switchboard_feeds() {
+        let wad = WAD as i128;
+        let mut feed = switchboard_account(150 * wad, wad / 10, NOW);
+        let config = oracle_config(OracleSource::SwitchboardOnDemand, feed.key);
+
+        let price = get_price(&config, &feed.info(), &clock()).unwrap();
+        assert_eq!(price.price, Decimal::from(150));
+        assert_eq!(price.confidence, Decimal::from_scaled_val(WAD as u128 / 10));
+
+        let mut stale = switchboard_account(150 * wad, 0, NOW - 61);
+        let config = oracle_config(OracleSource::SwitchboardOnDemand, stale.key);
+        assert_eq!(
+            get_price(&config, &stale.info(), &clock()).unwrap_err(),
+            ErrorCode::StalePrice.into()
+        );
+
+        let mut negative = switchboard_account(-wad, 0, NOW);
+        let config = oracle_config(OracleSource::SwitchboardOnDemand, negative.key);
+        assert_eq!(
+            get_price(&config, &negative.info(), &clock()).unwrap_err(),
+            ErrorCode::InvalidPrice.into()
+        );
+    }
+
+    #[test]
+    fn values_tokens() {
+        let price = Decimal::from(150);
+
+        let value = token_value(Decimal::from(2_500_000_000), price, 9).unwrap();
+        assert_eq!(value, Decimal::from(375));
+        assert_eq!(
+            token_amount(value, price, 9).unwrap(),
+            Decimal::from(2_500_000_000)
+        );
+    }
+
+    #[test]
+    fn values_u64_max_amounts() {
+        // u64::MAX base units of a 6 decimal token at $1M, the last digits are exact
+        let value = token_value(Decimal::from(u64::MAX), Decimal::from(1_000_000), 6).unwrap();
+        assert_eq!(value, Decimal::from(u64::MAX));
+
+        // Same amount of a token without decimals only fits up to a price of about $340
+        assert!(token_value(Decimal::from(u64::MAX), Decimal::from(300), 0).is_ok());
+        assert!(token_value(Decimal::from(u64::MAX), Decimal::from(400), 0).is_err());
+    }
+}
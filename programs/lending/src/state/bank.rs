@@ -1,20 +1,400 @@
 use anchor_lang::prelude::*;
 
+use crate::{
+    error::ErrorCode,
+    math::{compounded_interest_factor, shares_to_amount, Decimal, Rounding},
+    oracle::token_value,
+    BankConfig, InterestRateModel, OracleConfig, SECONDS_PER_YEAR,
+};
+
 #[account]
-#[derive(InitSpace)]
+#[derive(Default, InitSpace)]
 pub struct Bank {
-    pub authority: Pubkey,
+    pub lending_market: Pubkey,
     pub mint_address: Pubkey,
+    pub mint_decimals: u8,
+    // Pause flags, see `flags.rs`
+    pub flags: u64,
     pub total_deposits: u64,
     pub total_deposit_shares: u64,
     pub total_borrowed: u64,
     pub total_borrowed_shares: u64,
+    // Risk parameters, all in basis points
     pub liquidation_threshold: u64,
     pub liquidation_bonus: u64,
     pub liquidation_close_factor: u64,
     pub max_ltw: u64,
+    pub borrow_factor: u64,
     pub last_updated: i64,
-    pub interest_rate: u64,
+    pub last_updated_slot: u64,
+    pub interest_rate_model: InterestRateModel,
+    pub oracle: OracleConfig,
+    pub fallback_oracle: Option<OracleConfig>,
+    // Maximum gap between the primary and fallback prices, in basis points
+    pub max_price_divergence: u64,
+    // Caps on total deposits and borrows in tokens and in whole USD, zero leaves a cap off
+    pub deposit_limit: u64,
+    pub borrow_limit: u64,
+    pub deposit_limit_usd: u64,
+    pub borrow_limit_usd: u64,
+    // Origination fee added to every borrow, in basis points
+    pub borrow_fee: u64,
+    // Share of the origination fee paid to the borrow's host, in basis points
+    pub host_fee_share: u64,
+    // Fee charged on flash loans, in basis points
+    pub flash_loan_fee: u64,
+    // WAD-scaled amount of tokens owed per borrow share
+    pub cumulative_borrow_index: u128,
+    // WAD-scaled amount of tokens redeemable per deposit share
+    pub cumulative_supply_index: u128,
+    // WAD-scaled tokens owed to the protocol, held in the treasury until collected
+    pub accumulated_protocol_fees: u128,
+    // Token account protocol fees are collected into
+    pub fee_receiver: Pubkey,
     pub bank_bump: u8,
     pub treasury_bump: u8,
+    // Mint of the receipt tokens handed out for deposit shares
+    pub receipt_mint: Pubkey,
+    pub receipt_mint_bump: u8,
+    pub collateral_vault_bump: u8,
+}
+
+impl Bank {
+    pub fn borrow_index(&self) -> Decimal {
+        Decimal::from_scaled_val(self.cumulative_borrow_index)
+    }
+
+    pub fn supply_index(&self) -> Decimal {
+        Decimal::from_scaled_val(self.cumulative_supply_index)
+    }
+
+    pub fn protocol_fees(&self) -> Decimal {
+        Decimal::from_scaled_val(self.accumulated_protocol_fees)
+    }
+
+    // Origination fee on borrowing `amount` and the host's part of it, both rounded up
+    pub fn borrow_fees(&self, amount: u64) -> Result<(u64, u64)> {
+        let fee = Decimal::from(amount)
+            .try_mul(Decimal::from_bps(self.borrow_fee))?
+            .try_ceil_u64()?;
+        let host_fee = Decimal::from(fee)
+            .try_mul(Decimal::from_bps(self.host_fee_share))?
+            .try_floor_u64()?;
+
+        Ok((fee, host_fee))
+    }
+
+    // Share of deposits currently lent out, capped at 100%
+    pub fn utilization(&self) -> Result<Decimal> {
+        if self.total_deposits == 0 {
+            return Ok(Decimal::zero());
+        }
+
+        let utilization =
+            Decimal::from(self.total_borrowed).try_div(Decimal::from(self.total_deposits))?;
+
+        Ok(utilization.min(Decimal::one()))
+    }
+
+    pub fn borrow_rate(&self) -> Result<Decimal> {
+        self.interest_rate_model.borrow_rate(self.utilization()?)
+    }
+
+    pub fn supply_rate(&self) -> Result<Decimal> {
+        self.interest_rate_model.supply_rate(self.utilization()?)
+    }
+
+    // Supply rate compounded every second over a year
+    pub fn supply_apy(&self) -> Result<Decimal> {
+        compounded_interest_factor(self.supply_rate()?, SECONDS_PER_YEAR)?.try_sub(Decimal::one())
+    }
+
+    pub fn set_config(&mut self, config: &BankConfig) {
+        self.max_ltw = config.max_ltw;
+        self.liquidation_threshold = config.liquidation_threshold;
+        self.liquidation_bonus = config.liquidation_bonus;
+        self.liquidation_close_factor = config.liquidation_close_factor;
+        self.borrow_factor = config.borrow_factor;
+        self.interest_rate_model = config.interest_rate_model;
+        self.oracle = config.oracle;
+        self.fallback_oracle = config.fallback_oracle;
+        self.max_price_divergence = config.max_price_divergence;
+        self.deposit_limit = config.deposit_limit;
+        self.borrow_limit = config.borrow_limit;
+        self.deposit_limit_usd = config.deposit_limit_usd;
+        self.borrow_limit_usd = config.borrow_limit_usd;
+        self.borrow_fee = config.borrow_fee;
+        self.host_fee_share = config.host_fee_share;
+        self.flash_loan_fee = config.flash_loan_fee;
+    }
+
+    // `price` is only needed when a USD cap is set
+    pub fn check_deposit_limits(&self, price: Option<Decimal>) -> Result<()> {
+        if self.deposit_limit > 0 && self.total_deposits > self.deposit_limit {
+            return Err(ErrorCode::DepositLimitExceeded.into());
+        }
+
+        if self.deposit_limit_usd > 0 {
+            let price = price.ok_or(ErrorCode::InvalidOracleAccount)?;
+            let value = token_value(
+                Decimal::from(self.total_deposits),
+                price,
+                self.mint_decimals,
+            )?;
+
+            if value > Decimal::from(self.deposit_limit_usd) {
+                return Err(ErrorCode::DepositValueLimitExceeded.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn check_borrow_limits(&self, price: Decimal) -> Result<()> {
+        if self.borrow_limit > 0 && self.total_borrowed > self.borrow_limit {
+            return Err(ErrorCode::BorrowLimitExceeded.into());
+        }
+
+        if self.borrow_limit_usd > 0 {
+            let value = token_value(
+                Decimal::from(self.total_borrowed),
+                price,
+                self.mint_decimals,
+            )?;
+
+            if value > Decimal::from(self.borrow_limit_usd) {
+                return Err(ErrorCode::BorrowValueLimitExceeded.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn is_stale(&self, clock: &Clock) -> bool {
+        self.last_updated_slot != clock.slot
+    }
+
+    // Accrues interest up to the current time and marks the bank fresh for this slot
+    pub fn refresh(&mut self, clock: &Clock) -> Result<()> {
+        self.accrue_interest(clock.unix_timestamp)?;
+        self.last_updated_slot = clock.slot;
+
+        Ok(())
+    }
+
+    // Compounds the borrow index up to `current_time` and passes the interest, net of the
+    // reserve factor, on to depositors. The reserve factor share accrues to the protocol.
+    pub fn accrue_interest(&mut self, current_time: i64) -> Result<()> {
+        let elapsed = current_time.saturating_sub(self.last_updated);
+        if elapsed <= 0 {
+            return Ok(());
+        }
+
+        if self.total_borrowed_shares > 0 {
+            let growth = compounded_interest_factor(self.borrow_rate()?, elapsed as u64)?;
+
+            let borrowed =
+                Decimal::from(self.total_borrowed_shares).try_mul(self.borrow_index())?;
+            let interest = borrowed.try_mul(growth.try_sub(Decimal::one())?)?;
+            let depositor_interest = interest.try_mul(
+                Decimal::one()
+                    .try_sub(Decimal::from_bps(self.interest_rate_model.reserve_factor))?,
+            )?;
+
+            let mut protocol_fees = interest.try_sub(depositor_interest)?;
+
+            self.cumulative_borrow_index = self.borrow_index().try_mul(growth)?.to_scaled_val()?;
+
+            if self.total_deposit_shares == 0 {
+                protocol_fees = interest;
+            } else {
+                let deposited =
+                    Decimal::from(self.total_deposit_shares).try_mul(self.supply_index())?;
+                let supply_growth =
+                    Decimal::one().try_add(depositor_interest.try_div(deposited)?)?;
+
+                self.cumulative_supply_index = self
+                    .supply_index()
+                    .try_mul(supply_growth)?
+                    .to_scaled_val()?;
+            }
+
+            self.accumulated_protocol_fees = self
+                .protocol_fees()
+                .try_add(protocol_fees)?
+                .to_scaled_val()?;
+        }
+
+        self.sync_totals()?;
+        self.last_updated = current_time;
+
+        Ok(())
+    }
+
+    // Recomputes the token totals from the outstanding shares at the current indices
+    pub fn sync_totals(&mut self) -> Result<()> {
+        self.total_deposits = shares_to_amount(
+            self.total_deposit_shares,
+            self.supply_index(),
+            Rounding::Down,
+        )?;
+        self.total_borrowed = shares_to_amount(
+            self.total_borrowed_shares,
+            self.borrow_index(),
+            Rounding::Up,
+        )?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::WAD;
+
+    fn bank() -> Bank {
+        let data = [0u8; Bank::INIT_SPACE];
+        let mut bank = Bank::deserialize(&mut &data[..]).unwrap();
+
+        bank.cumulative_borrow_index = WAD as u128;
+        bank.cumulative_supply_index = WAD as u128;
+        bank.interest_rate_model = InterestRateModel {
+            base_rate: 0,
+            optimal_utilization: 8_000,
+            slope1: 400,
+            slope2: 7_500,
+            reserve_factor: 1_000,
+        };
+        bank
+    }
+
+    fn assert_close(actual: Decimal, expected_scaled: u128, tolerance: u128) {
+        let actual = actual.to_scaled_val().unwrap();
+        assert!(
+            actual.abs_diff(expected_scaled) <= tolerance,
+            "{actual} is not within {tolerance} of {expected_scaled}"
+        );
+    }
+
+    #[test]
+    fn accrues_a_year_of_interest() {
+        let mut bank = bank();
+        bank.total_deposit_shares = 1_000_000;
+        bank.total_borrowed_shares = 500_000;
+        bank.sync_totals().unwrap();
+
+        // 50% utilization on the 4% slope up to 80% borrows at 2.5%, compounding to e^0.025
+        assert_eq!(bank.borrow_rate().unwrap(), Decimal::from_bps(250));
+        bank.accrue_interest(SECONDS_PER_YEAR as i64).unwrap();
+
+        assert_eq!(bank.last_updated, SECONDS_PER_YEAR as i64);
+        assert_close(
+            bank.borrow_index(),
+            1_025_315_120_524_428_900,
+            1_000_000_000,
+        );
+
+        // 12657.56 tokens of interest, 10% of it withheld for the protocol
+        assert_close(
+            bank.supply_index(),
+            1_011_391_804_236_000_000,
+            1_000_000_000,
+        );
+        assert_close(
+            bank.protocol_fees(),
+            1_265_756_026_221_445_000_000,
+            1_000_000_000_000_000,
+        );
+
+        // Interest owed by borrowers equals what depositors and the protocol are credited
+        let borrowed = Decimal::from(500_000).try_mul(bank.borrow_index()).unwrap();
+        let deposited = Decimal::from(1_000_000)
+            .try_mul(bank.supply_index())
+            .unwrap();
+        let credited = deposited.try_add(bank.protocol_fees()).unwrap();
+        let interest = borrowed.try_sub(Decimal::from(500_000)).unwrap();
+        assert_close(
+            credited.try_sub(Decimal::from(1_000_000)).unwrap(),
+            interest.to_scaled_val().unwrap(),
+            1_000_000,
+        );
+
+        assert_eq!(bank.total_borrowed, 512_658);
+        assert_eq!(bank.total_deposits, 1_011_391);
+    }
+
+    #[test]
+    fn nothing_accrues_without_elapsed_time_or_borrows() {
+        let mut bank = bank();
+        bank.total_deposit_shares = 1_000_000;
+        bank.total_borrowed_shares = 500_000;
+        bank.last_updated = 100;
+
+        bank.accrue_interest(100).unwrap();
+        bank.accrue_interest(50).unwrap();
+        assert_eq!(bank.borrow_index(), Decimal::one());
+
+        bank.total_borrowed_shares = 0;
+        bank.accrue_interest(SECONDS_PER_YEAR as i64).unwrap();
+        assert_eq!(bank.supply_index(), Decimal::one());
+        assert_eq!(bank.protocol_fees(), Decimal::zero());
+    }
+
+    #[test]
+    fn enforces_token_and_usd_deposit_caps() {
+        let mut bank = bank();
+        bank.mint_decimals = 6;
+        bank.total_deposits = 2_000_000;
+        let price = Some(Decimal::from(100u64));
+
+        bank.deposit_limit = 2_000_000;
+        bank.check_deposit_limits(None).unwrap();
+        bank.deposit_limit = 1_999_999;
+        assert_eq!(
+            bank.check_deposit_limits(None).unwrap_err(),
+            ErrorCode::DepositLimitExceeded.into()
+        );
+
+        // 2 tokens at $100
+        bank.deposit_limit = 0;
+        bank.deposit_limit_usd = 200;
+        bank.check_deposit_limits(price).unwrap();
+        bank.deposit_limit_usd = 199;
+        assert_eq!(
+            bank.check_deposit_limits(price).unwrap_err(),
+            ErrorCode::DepositValueLimitExceeded.into()
+        );
+
+        // A USD cap can't be checked without a price
+        assert_eq!(
+            bank.check_deposit_limits(None).unwrap_err(),
+            ErrorCode::InvalidOracleAccount.into()
+        );
+    }
+
+    #[test]
+    fn enforces_token_and_usd_borrow_caps() {
+        let mut bank = bank();
+        bank.mint_decimals = 6;
+        bank.total_borrowed = 2_000_000;
+        let price = Decimal::from(100u64);
+
+        bank.borrow_limit = 2_000_000;
+        bank.check_borrow_limits(price).unwrap();
+        bank.borrow_limit = 1_999_999;
+        assert_eq!(
+            bank.check_borrow_limits(price).unwrap_err(),
+            ErrorCode::BorrowLimitExceeded.into()
+        );
+
+        bank.borrow_limit = 0;
+        bank.borrow_limit_usd = 200;
+        bank.check_borrow_limits(price).unwrap();
+        bank.borrow_limit_usd = 199;
+        assert_eq!(
+            bank.check_borrow_limits(price).unwrap_err(),
+            ErrorCode::BorrowValueLimitExceeded.into()
+        );
+    }
 }
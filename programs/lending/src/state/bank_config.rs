@@ -0,0 +1,75 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, math::BPS_SCALE, InterestRateModel, OracleConfig};
+
+// Every parameter the market's risk admin can tune, risk parameters in basis points
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct BankConfig {
+    pub max_ltw: u64,
+    pub liquidation_threshold: u64,
+    pub liquidation_bonus: u64,
+    pub liquidation_close_factor: u64,
+    pub borrow_factor: u64,
+    pub interest_rate_model: InterestRateModel,
+    pub oracle: OracleConfig,
+    pub fallback_oracle: Option<OracleConfig>,
+    pub max_price_divergence: u64,
+    pub deposit_limit: u64,
+    pub borrow_limit: u64,
+    pub deposit_limit_usd: u64,
+    pub borrow_limit_usd: u64,
+    pub borrow_fee: u64,
+    pub host_fee_share: u64,
+    pub flash_loan_fee: u64,
+}
+
+impl BankConfig {
+    pub fn validate(&self) -> Result<()> {
+        // Borrowing has to stop before a position can be liquidated, unless the asset isn't
+        // collateral at all
+        require!(
+            self.max_ltw < self.liquidation_threshold
+                || (self.max_ltw == 0 && self.liquidation_threshold == 0),
+            ErrorCode::InvalidBankConfig
+        );
+
+        // Seizing collateral plus the bonus must still leave the position better collateralized
+        let max_seized = (self.liquidation_threshold as u128)
+            .checked_mul(BPS_SCALE as u128 + self.liquidation_bonus as u128)
+            .ok_or(ErrorCode::MathOverflow)?;
+        require!(
+            max_seized < (BPS_SCALE as u128) * (BPS_SCALE as u128),
+            ErrorCode::InvalidBankConfig
+        );
+
+        require!(
+            self.liquidation_close_factor > 0 && self.liquidation_close_factor <= BPS_SCALE,
+            ErrorCode::InvalidBankConfig
+        );
+        require!(
+            self.borrow_factor >= BPS_SCALE,
+            ErrorCode::InvalidBankConfig
+        );
+
+        require!(
+            self.borrow_fee <= BPS_SCALE
+                && self.host_fee_share <= BPS_SCALE
+                && self.flash_loan_fee <= BPS_SCALE,
+            ErrorCode::InvalidBankConfig
+        );
+
+        self.interest_rate_model.validate()?;
+        self.oracle.validate()?;
+
+        if let Some(fallback_oracle) = &self.fallback_oracle {
+            fallback_oracle.validate()?;
+
+            require!(
+                self.max_price_divergence > 0 && self.max_price_divergence <= BPS_SCALE,
+                ErrorCode::InvalidOracleConfig
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use anchor_lang::prelude::*;
+
+use crate::error::ErrorCode;
+
+// Pause flags shared by banks and markets, a flag set on the market applies to all of its banks
+pub const DEPOSITS_PAUSED: u64 = 1 << 0;
+pub const BORROWS_PAUSED: u64 = 1 << 1;
+pub const WITHDRAWALS_PAUSED: u64 = 1 << 2;
+pub const LIQUIDATIONS_PAUSED: u64 = 1 << 3;
+// Only repays, withdrawals and liquidations go through
+pub const REDUCE_ONLY: u64 = 1 << 4;
+
+pub const ALL_FLAGS: u64 =
+    DEPOSITS_PAUSED | BORROWS_PAUSED | WITHDRAWALS_PAUSED | LIQUIDATIONS_PAUSED | REDUCE_ONLY;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Deposit,
+    Withdraw,
+    Borrow,
+    Repay,
+    Liquidate,
+}
+
+impl Operation {
+    // Flags that block this operation, repaying is never blocked so positions can always be closed
+    fn blocking_flags(&self) -> u64 {
+        match self {
+            Operation::Deposit => DEPOSITS_PAUSED | REDUCE_ONLY,
+            Operation::Withdraw => WITHDRAWALS_PAUSED,
+            Operation::Borrow => BORROWS_PAUSED | REDUCE_ONLY,
+            Operation::Repay => 0,
+            Operation::Liquidate => LIQUIDATIONS_PAUSED,
+        }
+    }
+}
+
+pub fn check_operation(market_flags: u64, bank_flags: u64, operation: Operation) -> Result<()> {
+    if (market_flags | bank_flags) & operation.blocking_flags() != 0 {
+        return Err(ErrorCode::OperationPaused.into());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, math::Decimal, math::BPS_SCALE, MAX_BORROW_RATE};
+
+// Kinked utilization curve, all values in basis points
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, InitSpace)]
+pub struct InterestRateModel {
+    pub base_rate: u64,
+    pub optimal_utilization: u64,
+    // Rate added between zero and optimal utilization
+    pub slope1: u64,
+    // Rate added between optimal and full utilization
+    pub slope2: u64,
+    // Share of borrow interest withheld from depositors
+    pub reserve_factor: u64,
+}
+
+impl InterestRateModel {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.optimal_utilization > 0 && self.optimal_utilization <= BPS_SCALE,
+            ErrorCode::InvalidInterestRateModel
+        );
+        require!(
+            self.reserve_factor <= BPS_SCALE,
+            ErrorCode::InvalidInterestRateModel
+        );
+
+        // The rate at full utilization is the highest the model can charge
+        let max_rate = self
+            .base_rate
+            .checked_add(self.slope1)
+            .and_then(|rate| rate.checked_add(self.slope2))
+            .ok_or(ErrorCode::InvalidInterestRateModel)?;
+        require!(
+            max_rate <= MAX_BORROW_RATE,
+            ErrorCode::InvalidInterestRateModel
+        );
+
+        Ok(())
+    }
+
+    // Annual borrow rate at the given utilization
+    pub fn borrow_rate(&self, utilization: Decimal) -> Result<Decimal> {
+        let base_rate = Decimal::from_bps(self.base_rate);
+        let optimal_utilization = Decimal::from_bps(self.optimal_utilization);
+        let slope1 = Decimal::from_bps(self.slope1);
+
+        if utilization <= optimal_utilization {
+            return base_rate.try_add(slope1.try_mul(utilization)?.try_div(optimal_utilization)?);
+        }
+
+        let excess_utilization = utilization
+            .try_sub(optimal_utilization)?
+            .try_div(Decimal::one().try_sub(optimal_utilization)?)?;
+
+        base_rate
+            .try_add(slope1)?
+            .try_add(Decimal::from_bps(self.slope2).try_mul(excess_utilization)?)
+    }
+
+    // Annual rate earned by depositors once the reserve factor is taken out
+    pub fn supply_rate(&self, utilization: Decimal) -> Result<Decimal> {
+        self.borrow_rate(utilization)?
+            .try_mul(utilization)?
+            .try_mul(Decimal::one().try_sub(Decimal::from_bps(self.reserve_factor))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> InterestRateModel {
+        InterestRateModel {
+            base_rate: 100,
+            optimal_utilization: 8_000,
+            slope1: 400,
+            slope2: 7_500,
+            reserve_factor: 1_000,
+        }
+    }
+
+    #[test]
+    fn follows_both_slopes_of_the_kink() {
+        let model = model();
+
+        // 40% utilization is halfway up the first slope: 1% + 4% / 2
+        assert_eq!(
+            model.borrow_rate(Decimal::from_bps(4_000)).unwrap(),
+            Decimal::from_bps(300)
+        );
+        assert_eq!(
+            model.borrow_rate(Decimal::from_bps(8_000)).unwrap(),
+            Decimal::from_bps(500)
+        );
+
+        // 90% utilization is halfway up the second slope: 1% + 4% + 75% / 2
+        assert_eq!(
+            model.borrow_rate(Decimal::from_bps(9_000)).unwrap(),
+            Decimal::from_bps(4_250)
+        );
+        assert_eq!(
+            model.borrow_rate(Decimal::one()).unwrap(),
+            Decimal::from_bps(8_000)
+        );
+
+        // Depositors earn the borrow rate on what is lent out, less the reserve factor
+        assert_eq!(
+            model.supply_rate(Decimal::from_bps(9_000)).unwrap(),
+            Decimal::from_bps(4_250)
+                .try_mul(Decimal::from_bps(9_000))
+                .unwrap()
+                .try_mul(Decimal::from_bps(9_000))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn caps_the_highest_rate() {
+        let mut model = model();
+        model.slope2 = MAX_BORROW_RATE - 500;
+        model.validate().unwrap();
+
+        model.slope2 += 1;
+        assert_eq!(
+            model.validate().unwrap_err(),
+            ErrorCode::InvalidInterestRateModel.into()
+        );
+
+        model.slope2 = u64::MAX;
+        assert_eq!(
+            model.validate().unwrap_err(),
+            ErrorCode::InvalidInterestRateModel.into()
+        );
+    }
+}
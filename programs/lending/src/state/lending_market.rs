@@ -0,0 +1,25 @@
+use anchor_lang::prelude::*;
+
+use crate::MAX_BANKS_PER_MARKET;
+
+// Groups banks and user accounts into an isolated pool, several markets can share a deployment
+#[account]
+#[derive(InitSpace)]
+pub struct LendingMarket {
+    // Lists banks and assigns the other roles, expected to be a multisig
+    pub owner: Pubkey,
+    // Proposed owner, takes over once it accepts
+    pub pending_owner: Pubkey,
+    // Tunes bank parameters and lifts pauses
+    pub risk_admin: Pubkey,
+    // Can only pause, meant to be a hot key
+    pub guardian: Pubkey,
+    // Collects protocol fees
+    pub fee_admin: Pubkey,
+    // Currency prices are quoted in, e.g. "USD" padded with zeroes
+    pub quote_currency: [u8; 32],
+    // Pause flags applied on top of every bank's own
+    pub flags: u64,
+    #[max_len(MAX_BANKS_PER_MARKET)]
+    pub banks: Vec<Pubkey>,
+}
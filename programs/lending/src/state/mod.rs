@@ -1,5 +1,17 @@
 pub mod bank;
+pub mod bank_config;
+pub mod flags;
+pub mod interest_rate_model;
+pub mod lending_market;
+pub mod oracle_config;
+pub mod test_oracle;
 pub mod user;
 
 pub use bank::*;
+pub use bank_config::*;
+pub use flags::*;
+pub use interest_rate_model::*;
+pub use lending_market::*;
+pub use oracle_config::*;
+pub use test_oracle::*;
 pub use user::*;
@@ -0,0 +1,67 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::ErrorCode, math::BPS_SCALE};
+
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq, Eq, InitSpace,
+)]
+pub enum OracleSource {
+    #[default]
+    PythPull,
+    SwitchboardOnDemand,
+    // Constant price, meant for stablecoins
+    FixedPrice,
+    // Program-owned price account, for tests and local development
+    TestOracle,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, InitSpace)]
+pub struct OracleConfig {
+    pub source: OracleSource,
+    // Pyth price feed id
+    pub feed_id: [u8; 32],
+    // Account the price has to be read from, any Pyth price update if left as default
+    pub price_account: Pubkey,
+    // Maximum age of a price in seconds
+    pub max_age: u64,
+    // Maximum confidence interval relative to the price, in basis points
+    pub max_confidence: u64,
+    // Value deposits at price - confidence and borrows at price + confidence
+    pub use_confidence_interval: bool,
+    // Price of a FixedPrice source, as fixed_price * 10^fixed_price_exponent
+    pub fixed_price: u64,
+    pub fixed_price_exponent: i32,
+}
+
+impl OracleConfig {
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            self.max_confidence <= BPS_SCALE,
+            ErrorCode::InvalidOracleConfig
+        );
+
+        // Test oracles are only accepted by builds made for testing
+        require!(
+            self.source != OracleSource::TestOracle || cfg!(feature = "test-oracle"),
+            ErrorCode::InvalidOracleConfig
+        );
+
+        match self.source {
+            OracleSource::PythPull => {
+                require!(self.max_age > 0, ErrorCode::InvalidOracleConfig);
+            }
+            OracleSource::SwitchboardOnDemand | OracleSource::TestOracle => {
+                require!(self.max_age > 0, ErrorCode::InvalidOracleConfig);
+                require!(
+                    self.price_account != Pubkey::default(),
+                    ErrorCode::InvalidOracleConfig
+                );
+            }
+            OracleSource::FixedPrice => {
+                require!(self.fixed_price > 0, ErrorCode::InvalidOracleConfig);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,12 @@
+use anchor_lang::prelude::*;
+
+// Price account whose value is set directly by the current owner of its market
+#[account]
+#[derive(InitSpace)]
+pub struct TestOracle {
+    pub lending_market: Pubkey,
+    pub price: i64,
+    pub exponent: i32,
+    pub confidence: u64,
+    pub publish_time: i64,
+}
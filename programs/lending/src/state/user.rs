@@ -1,20 +1,156 @@
 use anchor_lang::prelude::*;
 
+use crate::{error::ErrorCode, MAX_BORROW_POSITIONS, MAX_DEPOSIT_POSITIONS};
+
 #[account]
 #[derive(InitSpace)]
 pub struct User {
     pub owner: Pubkey,
-    pub deposited_sol: u64,
-    pub deposited_sol_shares: u64,
-    pub borrowed_sol: u64,
-    pub borrowed_sol_shares: u64,
-    pub deposited_usdc: u64,
-    pub deposited_usdc_shares: u64,
-    pub borrowed_usdc: u64,
-    pub borrowed_usdc_shares: u64,
-    pub usdc_address: Pubkey,
+    pub lending_market: Pubkey,
+    // Lets an owner hold several user accounts in the same market
+    pub id: u64,
+    #[max_len(MAX_DEPOSIT_POSITIONS)]
+    pub deposits: Vec<Position>,
+    #[max_len(MAX_BORROW_POSITIONS)]
+    pub borrows: Vec<Position>,
     pub health_factor: u64,
     pub last_updated: i64,
     pub last_updated_borrowed: i64,
     pub bump: u8,
 }
+
+// Shares held in a single bank
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, InitSpace)]
+pub struct Position {
+    pub bank: Pubkey,
+    pub shares: u64,
+}
+
+impl User {
+    pub fn deposit_shares(&self, bank: &Pubkey) -> u64 {
+        find_shares(&self.deposits, bank)
+    }
+
+    pub fn borrow_shares(&self, bank: &Pubkey) -> u64 {
+        find_shares(&self.borrows, bank)
+    }
+
+    pub fn add_deposit_shares(&mut self, bank: Pubkey, shares: u64) -> Result<()> {
+        add_shares(&mut self.deposits, bank, shares, MAX_DEPOSIT_POSITIONS)
+    }
+
+    pub fn add_borrow_shares(&mut self, bank: Pubkey, shares: u64) -> Result<()> {
+        add_shares(&mut self.borrows, bank, shares, MAX_BORROW_POSITIONS)
+    }
+
+    pub fn remove_deposit_shares(&mut self, bank: &Pubkey, shares: u64) -> Result<()> {
+        remove_shares(&mut self.deposits, bank, shares)
+    }
+
+    pub fn remove_borrow_shares(&mut self, bank: &Pubkey, shares: u64) -> Result<()> {
+        remove_shares(&mut self.borrows, bank, shares)
+    }
+}
+
+fn find_shares(positions: &[Position], bank: &Pubkey) -> u64 {
+    positions
+        .iter()
+        .find(|position| position.bank == *bank)
+        .map_or(0, |position| position.shares)
+}
+
+fn add_shares(
+    positions: &mut Vec<Position>,
+    bank: Pubkey,
+    shares: u64,
+    max_positions: usize,
+) -> Result<()> {
+    // An empty position could never be closed and would hold a slot for good
+    if shares == 0 {
+        return Err(ErrorCode::ZeroShares.into());
+    }
+
+    if let Some(position) = positions.iter_mut().find(|position| position.bank == bank) {
+        position.shares = position
+            .shares
+            .checked_add(shares)
+            .ok_or(ErrorCode::MathOverflow)?;
+        return Ok(());
+    }
+
+    if positions.len() >= max_positions {
+        return Err(ErrorCode::PositionLimitReached.into());
+    }
+
+    positions.push(Position { bank, shares });
+
+    Ok(())
+}
+
+// Closes the position once all of its shares are removed
+fn remove_shares(positions: &mut Vec<Position>, bank: &Pubkey, shares: u64) -> Result<()> {
+    let index = positions
+        .iter()
+        .position(|position| position.bank == *bank)
+        .ok_or(ErrorCode::PositionNotFound)?;
+
+    let position = &mut positions[index];
+    position.shares = position
+        .shares
+        .checked_sub(shares)
+        .ok_or(ErrorCode::MathOverflow)?;
+
+    if position.shares == 0 {
+        positions.remove(index);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> User {
+        User {
+            owner: Pubkey::default(),
+            lending_market: Pubkey::default(),
+            id: 0,
+            deposits: Vec::new(),
+            borrows: Vec::new(),
+            health_factor: u64::MAX,
+            last_updated: 0,
+            last_updated_borrowed: 0,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn zero_shares_never_open_a_position() {
+        let mut user = user();
+        let bank = Pubkey::new_unique();
+
+        assert_eq!(
+            user.add_borrow_shares(bank, 0).unwrap_err(),
+            ErrorCode::ZeroShares.into()
+        );
+        assert_eq!(
+            user.add_deposit_shares(bank, 0).unwrap_err(),
+            ErrorCode::ZeroShares.into()
+        );
+        assert!(user.borrows.is_empty() && user.deposits.is_empty());
+    }
+
+    #[test]
+    fn positions_close_once_empty() {
+        let mut user = user();
+        let bank = Pubkey::new_unique();
+
+        user.add_borrow_shares(bank, 10).unwrap();
+        user.add_borrow_shares(bank, 5).unwrap();
+        assert_eq!(user.borrow_shares(&bank), 15);
+
+        user.remove_borrow_shares(&bank, 15).unwrap();
+        assert!(user.borrows.is_empty());
+    }
+}
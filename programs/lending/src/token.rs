@@ -0,0 +1,92 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::{
+    self,
+    spl_token_2022::{
+        extension::{
+            transfer_fee::TransferFeeConfig, BaseStateWithExtensions, ExtensionType,
+            StateWithExtensions,
+        },
+        onchain::invoke_transfer_checked,
+        state::Mint,
+    },
+};
+
+use crate::error::ErrorCode;
+
+// Token-2022 extensions that let someone other than the holder move the bank's tokens or lock them
+const DISALLOWED_EXTENSIONS: [ExtensionType; 2] = [
+    ExtensionType::PermanentDelegate,
+    ExtensionType::NonTransferable,
+];
+
+// Rejects mints the bank couldn't safely hold, whichever token program owns them: a freeze
+// authority could lock the treasury, and so could the disallowed Token-2022 extensions. Transfer
+// fees, transfer hooks and interest-bearing mints are supported.
+pub fn check_mint_extensions(mint_info: &AccountInfo) -> Result<()> {
+    // Classic mints unpack as Token-2022 mints without extensions
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+
+    if mint.base.freeze_authority.is_some() {
+        return Err(ErrorCode::UnsupportedMint.into());
+    }
+
+    let extensions = mint.get_extension_types()?;
+    if extensions
+        .iter()
+        .any(|extension| DISALLOWED_EXTENSIONS.contains(extension))
+    {
+        return Err(ErrorCode::UnsupportedMint.into());
+    }
+
+    Ok(())
+}
+
+// Amount to send so that `amount` arrives after the mint's transfer fee
+pub fn amount_with_transfer_fee(mint_info: &AccountInfo, amount: u64) -> Result<u64> {
+    if *mint_info.owner != token_2022::ID {
+        return Ok(amount);
+    }
+
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+
+    let fee = match mint.get_extension::<TransferFeeConfig>() {
+        Ok(config) => config
+            .calculate_inverse_epoch_fee(Clock::get()?.epoch, amount)
+            .ok_or(ErrorCode::MathOverflow)?,
+        Err(_) => 0,
+    };
+
+    amount
+        .checked_add(fee)
+        .ok_or(ErrorCode::MathOverflow.into())
+}
+
+// `transfer_checked` that also resolves the accounts of the mint's transfer hook, if it has one,
+// from `extra_accounts`
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_tokens<'info>(
+    token_program: &AccountInfo<'info>,
+    from: AccountInfo<'info>,
+    mint: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    extra_accounts: &[AccountInfo<'info>],
+    amount: u64,
+    decimals: u8,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    invoke_transfer_checked(
+        token_program.key,
+        from,
+        mint,
+        to,
+        authority,
+        extra_accounts,
+        amount,
+        decimals,
+        signer_seeds,
+    )
+    .map_err(Into::into)
+}
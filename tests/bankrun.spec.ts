@@ -1,18 +1,39 @@
+import assert from "node:assert";
 import { describe, it } from "node:test";
 import { BN, Program } from "@coral-xyz/anchor";
 import { BankrunProvider } from "anchor-bankrun";
-import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import {
+  AccountLayout,
+  ExtensionType,
+  MINT_SIZE,
+  MintLayout,
+  NATIVE_MINT,
+  TOKEN_2022_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  createAssociatedTokenAccountIdempotentInstruction,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  createSyncNativeInstruction,
+  getAssociatedTokenAddressSync,
+  getMintLen,
+} from "@solana/spl-token";
 import { createAccount, createMint, mintTo } from "spl-token-bankrun";
-import { PythSolanaReceiver } from "@pythnetwork/pyth-solana-receiver";
 
 import { startAnchor, BanksClient, ProgramTestContext } from "solana-bankrun";
 
-import { PublicKey, Keypair, Connection } from "@solana/web3.js";
+import {
+  LAMPORTS_PER_SOL,
+  PublicKey,
+  Keypair,
+  SystemProgram,
+  Transaction,
+} from "@solana/web3.js";
 
 // @ts-ignore
 import IDL from "../target/idl/lending.json";
 import { Lending } from "../target/types/lending";
 import { BankrunContextWrapper } from "../bankrun-utils/bankrunConnection";
+import { setPythPriceUpdate } from "../bankrun-utils/pythPriceUpdate";
 
 describe("Lending Smart Contract Tests", async () => {
   let signer: Keypair;
@@ -26,46 +47,30 @@ describe("Lending Smart Contract Tests", async () => {
   let context: ProgramTestContext;
   let bankrunContextWrapper: BankrunContextWrapper;
 
-  const pyth = new PublicKey("7UVimffxr9ow1uXYxsr4LHAcV58mLzhmwaeKvJ1pjLiE");
-
-  const devnetConnection = new Connection("https://api.devnet.solana.com");
-  const accountInfo = await devnetConnection.getAccountInfo(pyth);
-
   context = await startAnchor(
     "",
     [{ name: "lending", programId: new PublicKey(IDL.address) }],
-    [
-      {
-        address: pyth,
-        info: accountInfo,
-      },
-    ]
+    []
   );
   provider = new BankrunProvider(context);
 
   bankrunContextWrapper = new BankrunContextWrapper(context);
 
-  const connection = bankrunContextWrapper.connection.toConnection();
-
-  const pythSolanaReceiver = new PythSolanaReceiver({
-    connection,
-    wallet: provider.wallet,
-  });
-
-  const SOL_PRICE_FEED_ID = "0xeaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a";
-
-  const solUsdPriceFeedAccount = pythSolanaReceiver
-    .getPriceFeedAccountAddress(0, SOL_PRICE_FEED_ID)
-    .toBase58();
+  const SOL_PRICE_FEED_ID = "0xef0d8b6fda2ceba41da15d4095d1da392a0d2f8ed0c6c7bc0f4cfac8c280b56d";
+  const USDC_PRICE_FEED_ID = "0xeaa020c61cc479712813461ce153894a96a6c00b21ed0cfc2798d1f9a9e9c94a";
 
-  const solUsdPriceFeedAccountPubkey = new PublicKey(solUsdPriceFeedAccount);
-  const feedAccountInfo = await devnetConnection.getAccountInfo(solUsdPriceFeedAccountPubkey);
+  // Price updates are fabricated locally so the tests never touch the network
+  const solUsdPriceFeedAccount = PublicKey.unique();
+  const usdcUsdPriceFeedAccount = PublicKey.unique();
 
-  context.setAccount(solUsdPriceFeedAccountPubkey, feedAccountInfo);
-
-  console.log("pricefeed:", solUsdPriceFeedAccount);
-
-  console.log("Pyth Account Info:", accountInfo);
+  await setPythPriceUpdate(context, solUsdPriceFeedAccount, SOL_PRICE_FEED_ID, {
+    price: 150 * 10 ** 8,
+    confidence: 5 * 10 ** 6,
+  });
+  await setPythPriceUpdate(context, usdcUsdPriceFeedAccount, USDC_PRICE_FEED_ID, {
+    price: 10 ** 8,
+    confidence: 10 ** 4,
+  });
 
   program = new Program<Lending>(IDL as Lending, provider);
 
@@ -91,29 +96,261 @@ describe("Lending Smart Contract Tests", async () => {
     2
   );
 
+  const lendingMarket = Keypair.generate();
+
+  // Hold their roles until "Test Update Bank Config" hands every role to the signer
+  const guardian = Keypair.generate();
+  const feeAdmin = Keypair.generate();
+
+  const [usdcBank] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("bank"),
+      lendingMarket.publicKey.toBuffer(),
+      mintUSDC.toBuffer(),
+    ],
+    program.programId
+  );
+
+  const [solBank] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("bank"),
+      lendingMarket.publicKey.toBuffer(),
+      mintSOL.toBuffer(),
+    ],
+    program.programId
+  );
+
   [usdcBankAccount] = PublicKey.findProgramAddressSync(
-    [Buffer.from("treasury"), mintUSDC.toBuffer()],
+    [Buffer.from("treasury"), usdcBank.toBuffer()],
     program.programId
   );
 
   [solBankAccount] = PublicKey.findProgramAddressSync(
-    [Buffer.from("treasury"), mintSOL.toBuffer()],
+    [Buffer.from("treasury"), solBank.toBuffer()],
     program.programId
   );
 
   [solTokenAccount] = PublicKey.findProgramAddressSync(
-    [Buffer.from("treasury"), mintSOL.toBuffer()],
+    [Buffer.from("treasury"), solBank.toBuffer()],
     program.programId
   );
 
   console.log("USDC Bank Account", usdcBankAccount.toBase58());
 
   console.log("SOL Bank Account", solBankAccount.toBase58());
+
+  const [usdcReceiptMint] = PublicKey.findProgramAddressSync(
+    [Buffer.from("receipt_mint"), usdcBank.toBuffer()],
+    program.programId
+  );
+
+  const userId = new BN(0);
+  const [userAccount] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("obligation"),
+      lendingMarket.publicKey.toBuffer(),
+      signer.publicKey.toBuffer(),
+      userId.toArrayLike(Buffer, "le", 8),
+    ],
+    program.programId
+  );
+
+  // Banks have to be refreshed in the same slot as any instruction that touches them
+  const refreshBank = (bank: PublicKey) =>
+    program.methods.refreshBank().accounts({ bank }).instruction();
+
+  const oracleConfig = (feedId: string, priceAccount: PublicKey) => ({
+    source: { pythPull: {} },
+    feedId: Array.from(Buffer.from(feedId.slice(2), "hex")),
+    priceAccount,
+    maxAge: new BN(100),
+    maxConfidence: new BN(200),
+    useConfidenceInterval: false,
+    fixedPrice: new BN(0),
+    fixedPriceExponent: 0,
+  });
+
+  // Only accepted by builds with the `test-oracle` feature, see the `test` script
+  const [solTestOracle] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from("test_oracle"),
+      lendingMarket.publicKey.toBuffer(),
+      mintSOL.toBuffer(),
+    ],
+    program.programId
+  );
+
+  const testOracleConfig = (priceAccount: PublicKey) => ({
+    ...oracleConfig("0x" + "00".repeat(32), priceAccount),
+    source: { testOracle: {} },
+  });
+
+  // Health checks take every bank of the user, in any order, followed by its primary and fallback
+  // price accounts
+  const healthAccounts = (groups: PublicKey[][]) =>
+    groups.flat().map((pubkey) => ({
+      pubkey,
+      isSigner: false,
+      isWritable: false,
+    }));
+
+  const interestRateModel = {
+    baseRate: new BN(0),
+    optimalUtilization: new BN(8_000),
+    slope1: new BN(400),
+    slope2: new BN(7_500),
+    reserveFactor: new BN(1_000),
+  };
+
+  const bankConfig = (oracle: ReturnType<typeof oracleConfig>) => ({
+    maxLtw: new BN(8_000),
+    liquidationThreshold: new BN(8_500),
+    liquidationBonus: new BN(500),
+    liquidationCloseFactor: new BN(5_000),
+    borrowFactor: new BN(10_000),
+    interestRateModel,
+    oracle,
+    fallbackOracle: null,
+    maxPriceDivergence: new BN(0),
+    depositLimit: new BN(0),
+    borrowLimit: new BN(0),
+    depositLimitUsd: new BN(0),
+    borrowLimitUsd: new BN(0),
+    borrowFee: new BN(10),
+    hostFeeShare: new BN(2_000),
+    flashLoanFee: new BN(9),
+  });
+
+  // Every bank is seeded by its creator with a deposit that stays locked in the bank
+  const seedAmount = new BN(1_000);
+
+  const fundSeedDeposit = async (
+    mint: PublicKey,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID
+  ) => {
+    const signerTokenAccount = await createAccount(
+      // @ts-ignores
+      banksClient,
+      signer,
+      mint,
+      signer.publicKey,
+      undefined,
+      tokenProgram
+    );
+
+    await mintTo(
+      // @ts-ignores
+      banksClient,
+      signer,
+      mint,
+      signerTokenAccount,
+      signer,
+      BigInt(seedAmount.toString()),
+      [],
+      tokenProgram
+    );
+
+    return signerTokenAccount;
+  };
+
+  it("Test Init Lending Market", async () => {
+    const quoteCurrency = Array.from(Buffer.alloc(32));
+    Buffer.from("USD").forEach((byte, i) => (quoteCurrency[i] = byte));
+
+    const initMarketTx = await program.methods
+      .initializeLendingMarket(quoteCurrency)
+      .accounts({
+        owner: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+      })
+      .signers([lendingMarket])
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Create Lending Market", initMarketTx);
+  });
+
+  it("Test Market Roles", async () => {
+    const stranger = Keypair.generate();
+
+    // Only the owner assigns roles
+    await assert.rejects(
+      program.methods
+        .setMarketRoles(
+          stranger.publicKey,
+          stranger.publicKey,
+          stranger.publicKey
+        )
+        .accounts({
+          owner: stranger.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+        })
+        .signers([stranger])
+        .rpc({ commitment: "confirmed" }),
+      /Unauthorized/
+    );
+
+    const setRolesTx = await program.methods
+      .setMarketRoles(signer.publicKey, guardian.publicKey, feeAdmin.publicKey)
+      .accounts({
+        owner: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Set Market Roles", setRolesTx);
+
+    // Only the owner proposes a new owner, and only that key can accept
+    await assert.rejects(
+      program.methods
+        .proposeMarketOwner(stranger.publicKey)
+        .accounts({
+          owner: stranger.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+        })
+        .signers([stranger])
+        .rpc({ commitment: "confirmed" }),
+      /Unauthorized/
+    );
+
+    const proposeOwnerTx = await program.methods
+      .proposeMarketOwner(signer.publicKey)
+      .accounts({
+        owner: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Propose Market Owner", proposeOwnerTx);
+
+    await assert.rejects(
+      program.methods
+        .acceptMarketOwner()
+        .accounts({
+          pendingOwner: stranger.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+        })
+        .signers([stranger])
+        .rpc({ commitment: "confirmed" }),
+      /Unauthorized/
+    );
+
+    const acceptOwnerTx = await program.methods
+      .acceptMarketOwner()
+      .accounts({
+        pendingOwner: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Accept Market Owner", acceptOwnerTx);
+  });
+
   it("Test Init User", async () => {
     const initUserTx = await program.methods
-      .initializeUser(mintUSDC)
+      .initializeUser(userId)
       .accounts({
         signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
       })
       .rpc({ commitment: "confirmed" });
 
@@ -121,11 +358,18 @@ describe("Lending Smart Contract Tests", async () => {
   });
 
   it("Test Init and Fund USDC Bank", async () => {
+    const signerTokenAccount = await fundSeedDeposit(mintUSDC);
+
     const initUSDCBankTx = await program.methods
-      .initializeBank(new BN(1), new BN(1))
+      .initializeBank(
+        bankConfig(oracleConfig(USDC_PRICE_FEED_ID, usdcUsdPriceFeedAccount)),
+        seedAmount
+      )
       .accounts({
         signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
         mint: mintUSDC,
+        signerTokenAccount,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
       .rpc({ commitment: "confirmed" });
@@ -147,11 +391,18 @@ describe("Lending Smart Contract Tests", async () => {
   });
 
   it("Test Init amd Fund SOL Bank", async () => {
+    const signerTokenAccount = await fundSeedDeposit(mintSOL);
+
     const initSOLBankTx = await program.methods
-      .initializeBank(new BN(1), new BN(1))
+      .initializeBank(
+        bankConfig(oracleConfig(SOL_PRICE_FEED_ID, solUsdPriceFeedAccount)),
+        seedAmount
+      )
       .accounts({
         signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
         mint: mintSOL,
+        signerTokenAccount,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
       .rpc({ commitment: "confirmed" });
@@ -172,17 +423,300 @@ describe("Lending Smart Contract Tests", async () => {
     console.log("Mint to SOL Bank Signature:", mintSOLTx);
   });
 
-  it("Create and Fund Token Account", async () => {
-    const USDCTokenAccount = await createAccount(
+  it("Test Reject Freezable Mints", async () => {
+    // A freeze authority is rejected under either token program
+    for (const tokenProgram of [TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID]) {
+      const freezableMint = await createMint(
+        // @ts-ignore
+        banksClient,
+        signer,
+        signer.publicKey,
+        signer.publicKey,
+        6,
+        undefined,
+        tokenProgram
+      );
+
+      const signerTokenAccount = await fundSeedDeposit(
+        freezableMint,
+        tokenProgram
+      );
+
+      await assert.rejects(
+        program.methods
+          .initializeBank(
+            bankConfig(
+              oracleConfig(USDC_PRICE_FEED_ID, usdcUsdPriceFeedAccount)
+            ),
+            seedAmount
+          )
+          .accounts({
+            signer: signer.publicKey,
+            lendingMarket: lendingMarket.publicKey,
+            mint: freezableMint,
+            signerTokenAccount,
+            tokenProgram,
+          })
+          .rpc({ commitment: "confirmed" }),
+        /UnsupportedMint/
+      );
+    }
+  });
+
+  it("Test Transfer Fee Mint Deposit Credits Received Amount", async () => {
+    // 1% of every transfer is withheld by the mint
+    const feeMint = Keypair.generate();
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    const rent = await banksClient.getRent();
+    const createFeeMintTx = new Transaction().add(
+      SystemProgram.createAccount({
+        fromPubkey: signer.publicKey,
+        newAccountPubkey: feeMint.publicKey,
+        space: mintLen,
+        lamports: Number(rent.minimumBalance(BigInt(mintLen))),
+        programId: TOKEN_2022_PROGRAM_ID,
+      }),
+      createInitializeTransferFeeConfigInstruction(
+        feeMint.publicKey,
+        signer.publicKey,
+        signer.publicKey,
+        100,
+        BigInt(1_000_000),
+        TOKEN_2022_PROGRAM_ID
+      ),
+      createInitializeMintInstruction(
+        feeMint.publicKey,
+        6,
+        signer.publicKey,
+        null,
+        TOKEN_2022_PROGRAM_ID
+      )
+    );
+    await provider.sendAndConfirm(createFeeMintTx, [feeMint]);
+
+    const signerTokenAccount = await fundSeedDeposit(
+      feeMint.publicKey,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    await program.methods
+      .initializeBank(
+        bankConfig(oracleConfig(USDC_PRICE_FEED_ID, usdcUsdPriceFeedAccount)),
+        seedAmount
+      )
+      .accounts({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: feeMint.publicKey,
+        signerTokenAccount,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const [feeBank] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("bank"),
+        lendingMarket.publicKey.toBuffer(),
+        feeMint.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    const [feeReceiptMint] = PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt_mint"), feeBank.toBuffer()],
+      program.programId
+    );
+
+    // Only the seed deposit minus its fee reached the treasury
+    const seeded = (await program.account.bank.fetch(feeBank)).totalDeposits;
+    assert.equal(seeded.toNumber(), 990);
+
+    await mintTo(
       // @ts-ignores
       banksClient,
       signer,
+      feeMint.publicKey,
+      signerTokenAccount,
+      signer,
+      100_000,
+      [],
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    await program.methods
+      .deposit(new BN(100_000), false)
+      .accountsPartial({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: feeMint.publicKey,
+        userTokenAccount: signerTokenAccount,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+      })
+      .preInstructions([await refreshBank(feeBank)])
+      .rpc({ commitment: "confirmed" });
+
+    // Credited with the 99_000 that arrived, not the 100_000 sent
+    const bank = await program.account.bank.fetch(feeBank);
+    assert.equal(bank.totalDeposits.sub(seeded).toNumber(), 99_000);
+
+    const receiptAccount = await banksClient.getAccount(
+      getAssociatedTokenAddressSync(
+        feeReceiptMint,
+        signer.publicKey,
+        false,
+        TOKEN_2022_PROGRAM_ID
+      )
+    );
+    const receipt = AccountLayout.decode(receiptAccount.data);
+    assert.equal(receipt.amount, BigInt(99_000));
+  });
+
+  it("Test Init Test Oracle", async () => {
+    // Only the market owner can create test oracles
+    const stranger = Keypair.generate();
+    await assert.rejects(
+      program.methods
+        .initializeTestOracle(new BN(1), -8, new BN(0))
+        .accounts({
+          owner: stranger.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          mint: mintSOL,
+        })
+        .signers([stranger])
+        .rpc({ commitment: "confirmed" })
+    );
+
+    const initTestOracleTx = await program.methods
+      .initializeTestOracle(new BN(150 * 10 ** 8), -8, new BN(5 * 10 ** 6))
+      .accounts({
+        owner: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: mintSOL,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Create SOL Test Oracle", initTestOracleTx);
+
+    // Prices follow the market's current owner, nobody else can set them
+    await assert.rejects(
+      program.methods
+        .setTestOraclePrice(new BN(1), -8, new BN(0), null)
+        .accounts({
+          owner: stranger.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          testOracle: solTestOracle,
+        })
+        .signers([stranger])
+        .rpc({ commitment: "confirmed" }),
+      /Unauthorized/
+    );
+
+    const setTestOraclePriceTx = await program.methods
+      .setTestOraclePrice(new BN(151 * 10 ** 8), -8, new BN(5 * 10 ** 6), null)
+      .accounts({
+        owner: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        testOracle: solTestOracle,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Set SOL Test Oracle Price", setTestOraclePriceTx);
+  });
+
+  it("Test Update Bank Config", async () => {
+    // Neither the guardian nor the fee admin can change a bank's parameters
+    for (const admin of [guardian, feeAdmin]) {
+      await assert.rejects(
+        program.methods
+          .updateBankConfig(
+            bankConfig(oracleConfig(SOL_PRICE_FEED_ID, solUsdPriceFeedAccount))
+          )
+          .accounts({
+            riskAdmin: admin.publicKey,
+            lendingMarket: lendingMarket.publicKey,
+            bank: solBank,
+          })
+          .signers([admin])
+          .rpc({ commitment: "confirmed" }),
+        /Unauthorized/
+      );
+    }
+
+    await program.methods
+      .setMarketRoles(signer.publicKey, signer.publicKey, signer.publicKey)
+      .accounts({
+        owner: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const updateSOLBankConfig = (config: ReturnType<typeof bankConfig>) =>
+      program.methods
+        .updateBankConfig(config)
+        .accounts({
+          riskAdmin: signer.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          bank: solBank,
+        })
+        .rpc({ commitment: "confirmed" });
+    const solConfig = bankConfig(
+      oracleConfig(SOL_PRICE_FEED_ID, solUsdPriceFeedAccount)
+    );
+
+    // Borrowing has to stop before the liquidation threshold
+    await assert.rejects(
+      updateSOLBankConfig({ ...solConfig, maxLtw: new BN(8_500) }),
+      /InvalidBankConfig/
+    );
+
+    // 90% seized with a 12% bonus would take more than the whole position
+    await assert.rejects(
+      updateSOLBankConfig({
+        ...solConfig,
+        liquidationThreshold: new BN(9_000),
+        liquidationBonus: new BN(1_200),
+      }),
+      /InvalidBankConfig/
+    );
+
+    const updateConfigTx = await program.methods
+      .updateBankConfig({
+        ...bankConfig({
+          ...oracleConfig(SOL_PRICE_FEED_ID, solUsdPriceFeedAccount),
+          maxAge: new BN(60),
+        }),
+        liquidationBonus: new BN(700),
+        depositLimit: new BN(1_000_000 * 10 ** 2),
+        borrowLimitUsd: new BN(5_000_000),
+        fallbackOracle: testOracleConfig(solTestOracle),
+        maxPriceDivergence: new BN(500),
+      })
+      .accounts({
+        riskAdmin: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        bank: solBank,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Update SOL Bank Config", updateConfigTx);
+  });
+
+  it("Test Refresh Banks", async () => {
+    const refreshTx = await program.methods
+      .refreshBank()
+      .accounts({ bank: usdcBank })
+      .postInstructions([await refreshBank(solBank)])
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Refresh Banks", refreshTx);
+  });
+
+  it("Create and Fund Token Account", async () => {
+    // Created when seeding the USDC bank
+    const USDCTokenAccount = getAssociatedTokenAddressSync(
       mintUSDC,
       signer.publicKey
     );
 
-    console.log("USDC Token Account Created:", USDCTokenAccount);
-
     const amount = 10_000 * 10 ** 9;
     const mintUSDCTx = await mintTo(
       // @ts-ignores
@@ -197,56 +731,999 @@ describe("Lending Smart Contract Tests", async () => {
     console.log("Mint to USDC Bank Signature:", mintUSDCTx);
   });
 
+  it("Test Set Fee Receiver", async () => {
+    const setFeeReceiverTx = await program.methods
+      .setFeeReceiver()
+      .accounts({
+        feeAdmin: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        bank: usdcBank,
+        feeReceiver: getAssociatedTokenAddressSync(mintUSDC, signer.publicKey),
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Set USDC Fee Receiver", setFeeReceiverTx);
+  });
+
   it("Test Deposit", async () => {
     const depositUSDC = await program.methods
-      .deposit(new BN(100000000000))
-      .accounts({
+      .deposit(new BN(100000000000), false)
+      .accountsPartial({
         signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
         mint: mintUSDC,
+        userTokenAccount: getAssociatedTokenAddressSync(
+          mintUSDC,
+          signer.publicKey
+        ),
         tokenProgram: TOKEN_PROGRAM_ID,
       })
+      .preInstructions([await refreshBank(usdcBank)])
       .rpc({ commitment: "confirmed" });
 
     console.log("Deposit USDC", depositUSDC);
   });
 
+  it("Test Deposit Collateral", async () => {
+    const depositCollateralTx = await program.methods
+      .depositCollateral(new BN(100000000000))
+      .accountsPartial({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        bank: usdcBank,
+        receiptMint: usdcReceiptMint,
+        userReceiptTokenAccount: getAssociatedTokenAddressSync(
+          usdcReceiptMint,
+          signer.publicKey
+        ),
+        userAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Deposit USDC Collateral", depositCollateralTx);
+  });
+
   it("Test Borrow", async () => {
     const borrowSOL = await program.methods
-      .borrow(new BN(1))
-      .accounts({
+      .borrow(new BN(1), false)
+      .accountsPartial({
         signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
         mint: mintSOL,
+        userAccount,
+        hostFeeReceiver: null,
         tokenProgram: TOKEN_PROGRAM_ID,
-        priceUpdate: solUsdPriceFeedAccount,
       })
+      .remainingAccounts(
+        healthAccounts([
+          [usdcBank, usdcUsdPriceFeedAccount],
+          [solBank, solUsdPriceFeedAccount, solTestOracle],
+        ])
+      )
+      .preInstructions([
+        await refreshBank(solBank),
+        await refreshBank(usdcBank),
+      ])
       .rpc({ commitment: "confirmed" });
 
     console.log("Borrow SOL", borrowSOL);
   });
 
+  it("Test Borrow with Host Fee", async () => {
+    // A front end names its own account to take a share of the origination fee
+    const hostUSDCAccount = await createAccount(
+      // @ts-ignores
+      banksClient,
+      signer,
+      mintUSDC,
+      Keypair.generate().publicKey
+    );
+
+    const amount = 1_000 * 10 ** 2;
+    const fee = Math.ceil((amount * 10) / 10_000);
+    const hostFee = Math.floor((fee * 2_000) / 10_000);
+    const wad = new BN(10).pow(new BN(18));
+
+    const bankBefore = await program.account.bank.fetch(usdcBank);
+    const borrowUSDC = await program.methods
+      .borrow(new BN(amount), false)
+      .accountsPartial({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: mintUSDC,
+        userAccount,
+        hostFeeReceiver: hostUSDCAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(
+        healthAccounts([
+          [usdcBank, usdcUsdPriceFeedAccount],
+          [solBank, solUsdPriceFeedAccount, solTestOracle],
+        ])
+      )
+      .preInstructions([
+        await refreshBank(usdcBank),
+        await refreshBank(solBank),
+      ])
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Borrow USDC with Host Fee", borrowUSDC);
+
+    const host = await banksClient.getAccount(hostUSDCAccount);
+    assert.equal(AccountLayout.decode(host.data).amount, BigInt(hostFee));
+
+    // The protocol keeps the rest of the fee and the whole fee is owed back
+    const bankAfter = await program.account.bank.fetch(usdcBank);
+    assert.ok(
+      new BN(bankAfter.accumulatedProtocolFees.toString())
+        .sub(new BN(bankBefore.accumulatedProtocolFees.toString()))
+        .eq(new BN(fee - hostFee).mul(wad))
+    );
+    assert.equal(
+      bankAfter.totalBorrowed.sub(bankBefore.totalBorrowed).toNumber(),
+      amount + fee
+    );
+
+    const user = await program.account.user.fetch(userAccount);
+    const position = user.borrows.find((position) =>
+      position.bank.equals(usdcBank)
+    );
+    assert.equal(position.shares.toNumber(), amount + fee);
+  });
+
+  it("Test Withdraw Collateral Beyond Health", async () => {
+    // Pulling all the collateral would leave the SOL borrow uncovered
+    await assert.rejects(
+      program.methods
+        .withdrawCollateral(new BN(100000000000))
+        .accountsPartial({
+          signer: signer.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          bank: usdcBank,
+          receiptMint: usdcReceiptMint,
+          userReceiptTokenAccount: getAssociatedTokenAddressSync(
+            usdcReceiptMint,
+            signer.publicKey
+          ),
+          userAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          healthAccounts([
+            [usdcBank, usdcUsdPriceFeedAccount],
+            [solBank, solUsdPriceFeedAccount, solTestOracle],
+          ])
+        )
+        .preInstructions([
+          await refreshBank(usdcBank),
+          await refreshBank(solBank),
+        ])
+        .rpc({ commitment: "confirmed" }),
+      /WithdrawalExceedsHealth/
+    );
+  });
+
   it("Test Repay", async () => {
     const repaySOL = await program.methods
-      .repay(new BN(1))
-      .accounts({
+      .repay(new BN(1), false)
+      .accountsPartial({
         signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
         mint: mintSOL,
+        userAccount,
+        userTokenAccount: getAssociatedTokenAddressSync(
+          mintSOL,
+          signer.publicKey
+        ),
         tokenProgram: TOKEN_PROGRAM_ID,
       })
+      .preInstructions([await refreshBank(solBank)])
       .rpc({ commitment: "confirmed" });
 
     console.log("Repay SOL", repaySOL);
   });
 
+  it("Test Withdraw Collateral", async () => {
+    const withdrawCollateralTx = await program.methods
+      .withdrawCollateral(new BN(100))
+      .accountsPartial({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        bank: usdcBank,
+        receiptMint: usdcReceiptMint,
+        userReceiptTokenAccount: getAssociatedTokenAddressSync(
+          usdcReceiptMint,
+          signer.publicKey
+        ),
+        userAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(
+        healthAccounts([
+          [usdcBank, usdcUsdPriceFeedAccount],
+          [solBank, solUsdPriceFeedAccount, solTestOracle],
+        ])
+      )
+      .preInstructions([
+        await refreshBank(usdcBank),
+        await refreshBank(solBank),
+      ])
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Withdraw USDC Collateral", withdrawCollateralTx);
+  });
+
+  it("Test Fallback Oracle", async () => {
+    // The SOL bank reads Pyth first and falls back to its test oracle
+    // Amounts differ between attempts so no transaction is sent twice
+    const withdrawCollateral = async (amount: number) =>
+      program.methods
+        .withdrawCollateral(new BN(amount))
+        .accountsPartial({
+          signer: signer.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          bank: usdcBank,
+          receiptMint: usdcReceiptMint,
+          userReceiptTokenAccount: getAssociatedTokenAddressSync(
+            usdcReceiptMint,
+            signer.publicKey
+          ),
+          userAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(
+          healthAccounts([
+            [usdcBank, usdcUsdPriceFeedAccount],
+            [solBank, solUsdPriceFeedAccount, solTestOracle],
+          ])
+        )
+        .preInstructions([
+          await refreshBank(usdcBank),
+          await refreshBank(solBank),
+        ])
+        .rpc({ commitment: "confirmed" });
+    const setSOLTestOraclePrice = (price: number) =>
+      program.methods
+        .setTestOraclePrice(
+          new BN(price * 10 ** 8),
+          -8,
+          new BN(5 * 10 ** 6),
+          null
+        )
+        .accounts({
+          owner: signer.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          testOracle: solTestOracle,
+        })
+        .rpc({ commitment: "confirmed" });
+    const setSOLPythPrice = async (publishTime?: number) =>
+      setPythPriceUpdate(context, solUsdPriceFeedAccount, SOL_PRICE_FEED_ID, {
+        price: 150 * 10 ** 8,
+        confidence: 5 * 10 ** 6,
+        publishTime,
+      });
+
+    // $170 is more than 5% away from Pyth's $150
+    await setSOLTestOraclePrice(170);
+    await assert.rejects(withdrawCollateral(1), /PriceDivergence/);
+
+    // Once the Pyth update goes stale the test oracle takes over on its own
+    const now = Number((await banksClient.getClock()).unixTimestamp);
+    await setSOLPythPrice(now - 120);
+    await withdrawCollateral(2);
+
+    await setSOLPythPrice();
+    await setSOLTestOraclePrice(150);
+  });
+
   it("Test Withdraw", async () => {
     const withdrawUSDC = await program.methods
-      .withdraw(new BN(100))
+      .withdraw(new BN(100), false)
       .accounts({
         signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
         mint: mintUSDC,
         tokenProgram: TOKEN_PROGRAM_ID,
       })
+      .preInstructions([await refreshBank(usdcBank)])
       .rpc({ commitment: "confirmed" });
 
     console.log("Withdraw USDC", withdrawUSDC);
   });
+
+  it("Test Deposit Limit", async () => {
+    // The SOL bank takes at most 1,000,000 SOL
+    const SOLTokenAccount = getAssociatedTokenAddressSync(
+      mintSOL,
+      signer.publicKey
+    );
+    const amount = 1_000_001 * 10 ** 2;
+    await mintTo(
+      // @ts-ignores
+      banksClient,
+      signer,
+      mintSOL,
+      SOLTokenAccount,
+      signer,
+      amount
+    );
+
+    await assert.rejects(
+      program.methods
+        .deposit(new BN(amount), false)
+        .accountsPartial({
+          signer: signer.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          mint: mintSOL,
+          userTokenAccount: SOLTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([await refreshBank(solBank)])
+        .rpc({ commitment: "confirmed" }),
+      /DepositLimitExceeded/
+    );
+  });
+
+  it("Test Native SOL Deposit, Withdraw, Borrow and Repay", async () => {
+    // The wrapped SOL mint isn't part of the bankrun genesis
+    if ((await banksClient.getAccount(NATIVE_MINT)) === null) {
+      const data = Buffer.alloc(MINT_SIZE);
+      MintLayout.encode(
+        {
+          mintAuthorityOption: 0,
+          mintAuthority: PublicKey.default,
+          supply: BigInt(0),
+          decimals: 9,
+          isInitialized: true,
+          freezeAuthorityOption: 0,
+          freezeAuthority: PublicKey.default,
+        },
+        data
+      );
+      context.setAccount(NATIVE_MINT, {
+        lamports: LAMPORTS_PER_SOL,
+        data,
+        owner: TOKEN_PROGRAM_ID,
+        executable: false,
+      });
+    }
+
+    const [nativeBank] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("bank"),
+        lendingMarket.publicKey.toBuffer(),
+        NATIVE_MINT.toBuffer(),
+      ],
+      program.programId
+    );
+    const [nativeReceiptMint] = PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt_mint"), nativeBank.toBuffer()],
+      program.programId
+    );
+    const nativeUserId = new BN(1);
+    const [nativeUserAccount] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("obligation"),
+        lendingMarket.publicKey.toBuffer(),
+        signer.publicKey.toBuffer(),
+        nativeUserId.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const wrappedSOLAccount = getAssociatedTokenAddressSync(
+      NATIVE_MINT,
+      signer.publicKey
+    );
+    const nativeReceiptAccount = getAssociatedTokenAddressSync(
+      nativeReceiptMint,
+      signer.publicKey
+    );
+    const rent = async (account: PublicKey) =>
+      Number((await banksClient.getAccount(account)).lamports);
+    const lamports = async () => banksClient.getBalance(signer.publicKey);
+    const nativeTotals = async () => {
+      const bank = await program.account.bank.fetch(nativeBank);
+      return {
+        deposits: bank.totalDeposits.toNumber(),
+        borrowed: bank.totalBorrowed.toNumber(),
+      };
+    };
+    // Transaction fees are paid by the signer as well
+    const assertLamportsMoved = (
+      before: bigint,
+      after: bigint,
+      amount: number
+    ) =>
+      assert.ok(
+        Math.abs(Number(after - before) - amount) <= 10_000,
+        `expected ${amount} lamports, moved ${after - before}`
+      );
+
+    // The bank creator wraps the seed deposit themselves
+    const wrapSeedTx = new Transaction().add(
+      createAssociatedTokenAccountIdempotentInstruction(
+        signer.publicKey,
+        wrappedSOLAccount,
+        signer.publicKey,
+        NATIVE_MINT
+      ),
+      SystemProgram.transfer({
+        fromPubkey: signer.publicKey,
+        toPubkey: wrappedSOLAccount,
+        lamports: seedAmount.toNumber(),
+      }),
+      createSyncNativeInstruction(wrappedSOLAccount)
+    );
+    await provider.sendAndConfirm(wrapSeedTx);
+
+    await program.methods
+      .initializeBank(
+        bankConfig(oracleConfig(SOL_PRICE_FEED_ID, solUsdPriceFeedAccount)),
+        seedAmount
+      )
+      .accounts({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: NATIVE_MINT,
+        signerTokenAccount: wrappedSOLAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    await program.methods
+      .initializeUser(nativeUserId)
+      .accounts({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    // Deposit lamports straight from the wallet, which also pays for the receipt token account
+    let before = await lamports();
+    await program.methods
+      .deposit(new BN(2 * LAMPORTS_PER_SOL), true)
+      .accountsPartial({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: NATIVE_MINT,
+        userTokenAccount: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([await refreshBank(nativeBank)])
+      .rpc({ commitment: "confirmed" });
+
+    assertLamportsMoved(
+      before,
+      await lamports(),
+      -2 * LAMPORTS_PER_SOL - (await rent(nativeReceiptAccount))
+    );
+    assert.equal(
+      (await nativeTotals()).deposits,
+      2 * LAMPORTS_PER_SOL + seedAmount.toNumber()
+    );
+
+    // Withdraw half a SOL back as lamports, closing the wrapped SOL account returns its rent too
+    const wrappedSOLRent = await rent(wrappedSOLAccount);
+    before = await lamports();
+    await program.methods
+      .withdraw(new BN(LAMPORTS_PER_SOL / 2), true)
+      .accounts({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: NATIVE_MINT,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([await refreshBank(nativeBank)])
+      .rpc({ commitment: "confirmed" });
+
+    assertLamportsMoved(
+      before,
+      await lamports(),
+      LAMPORTS_PER_SOL / 2 + wrappedSOLRent
+    );
+    assert.equal(await banksClient.getAccount(wrappedSOLAccount), null);
+    assert.equal(
+      (await nativeTotals()).deposits,
+      1.5 * LAMPORTS_PER_SOL + seedAmount.toNumber()
+    );
+
+    // Borrow against SOL collateral and receive lamports
+    await program.methods
+      .depositCollateral(new BN(LAMPORTS_PER_SOL))
+      .accountsPartial({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        bank: nativeBank,
+        receiptMint: nativeReceiptMint,
+        userReceiptTokenAccount: nativeReceiptAccount,
+        userAccount: nativeUserAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const borrowAmount = LAMPORTS_PER_SOL / 10;
+    before = await lamports();
+    await program.methods
+      .borrow(new BN(borrowAmount), true)
+      .accountsPartial({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: NATIVE_MINT,
+        userAccount: nativeUserAccount,
+        hostFeeReceiver: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(
+        healthAccounts([[nativeBank, solUsdPriceFeedAccount]])
+      )
+      .preInstructions([await refreshBank(nativeBank)])
+      .rpc({ commitment: "confirmed" });
+
+    assertLamportsMoved(before, await lamports(), borrowAmount);
+    assert.equal(await banksClient.getAccount(wrappedSOLAccount), null);
+
+    // The 10 bps origination fee is owed on top of the borrowed amount
+    const debt = borrowAmount + borrowAmount / 1_000;
+    assert.equal((await nativeTotals()).borrowed, debt);
+
+    // Repay part of it with lamports
+    const repayAmount = borrowAmount / 2;
+    before = await lamports();
+    await program.methods
+      .repay(new BN(repayAmount), true)
+      .accountsPartial({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: NATIVE_MINT,
+        userAccount: nativeUserAccount,
+        userTokenAccount: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([await refreshBank(nativeBank)])
+      .rpc({ commitment: "confirmed" });
+
+    assertLamportsMoved(before, await lamports(), -repayAmount);
+    assert.equal((await nativeTotals()).borrowed, debt - repayAmount);
+  });
+
+  it("Test Native SOL Rejected by Non-SOL Bank", async () => {
+    await assert.rejects(
+      program.methods
+        .deposit(new BN(1_000), true)
+        .accountsPartial({
+          signer: signer.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          mint: mintUSDC,
+          userTokenAccount: null,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([await refreshBank(usdcBank)])
+        .rpc({ commitment: "confirmed" })
+    );
+  });
+
+  it("Test Pause and Resume Deposits", async () => {
+    const DEPOSITS_PAUSED = new BN(1);
+
+    // Amounts differ between attempts so no transaction is sent twice
+    const depositUSDC = async (amount: number) =>
+      program.methods
+        .deposit(new BN(amount), false)
+        .accountsPartial({
+          signer: signer.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          mint: mintUSDC,
+          userTokenAccount: getAssociatedTokenAddressSync(
+            mintUSDC,
+            signer.publicKey
+          ),
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([await refreshBank(usdcBank)])
+        .rpc({ commitment: "confirmed" });
+
+    const pauseTx = await program.methods
+      .setBankFlags(DEPOSITS_PAUSED)
+      .accounts({
+        guardian: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        bank: usdcBank,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Pause USDC Deposits", pauseTx);
+
+    await assert.rejects(depositUSDC(1_000), /OperationPaused/);
+
+    // The guardian can only add flags, so this leaves deposits paused
+    await program.methods
+      .setBankFlags(new BN(0))
+      .accounts({
+        guardian: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        bank: usdcBank,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    await assert.rejects(depositUSDC(1_001), /OperationPaused/);
+
+    const resumeTx = await program.methods
+      .clearBankFlags(DEPOSITS_PAUSED)
+      .accounts({
+        admin: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        bank: usdcBank,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    console.log("Resume USDC Deposits", resumeTx);
+
+    await depositUSDC(1_002);
+
+    const bank = await program.account.bank.fetch(usdcBank);
+    assert.ok(bank.flags.isZero());
+  });
+
+  it("Test Liquidate", async () => {
+    // The collateral is priced by a test oracle so that its price can be dropped
+    const mintCOL = await createMint(
+      // @ts-ignore
+      banksClient,
+      signer,
+      signer.publicKey,
+      null,
+      6
+    );
+    const [colBank] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("bank"),
+        lendingMarket.publicKey.toBuffer(),
+        mintCOL.toBuffer(),
+      ],
+      program.programId
+    );
+    const [colReceiptMint] = PublicKey.findProgramAddressSync(
+      [Buffer.from("receipt_mint"), colBank.toBuffer()],
+      program.programId
+    );
+    const [colTestOracle] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("test_oracle"),
+        lendingMarket.publicKey.toBuffer(),
+        mintCOL.toBuffer(),
+      ],
+      program.programId
+    );
+
+    await program.methods
+      .initializeTestOracle(new BN(100 * 10 ** 8), -8, new BN(0))
+      .accounts({
+        owner: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: mintCOL,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    await program.methods
+      .initializeBank(bankConfig(testOracleConfig(colTestOracle)), seedAmount)
+      .accounts({
+        signer: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: mintCOL,
+        signerTokenAccount: await fundSeedDeposit(mintCOL),
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const borrower = Keypair.generate();
+    const keeper = Keypair.generate();
+    for (const account of [borrower, keeper]) {
+      context.setAccount(account.publicKey, {
+        lamports: 10 * LAMPORTS_PER_SOL,
+        data: Buffer.alloc(0),
+        owner: SystemProgram.programId,
+        executable: false,
+      });
+    }
+
+    const [borrowerAccount] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("obligation"),
+        lendingMarket.publicKey.toBuffer(),
+        borrower.publicKey.toBuffer(),
+        userId.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const health = () =>
+      healthAccounts([
+        [colBank, colTestOracle],
+        [usdcBank, usdcUsdPriceFeedAccount],
+      ]);
+
+    await program.methods
+      .initializeUser(userId)
+      .accounts({
+        signer: borrower.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+      })
+      .signers([borrower])
+      .rpc({ commitment: "confirmed" });
+
+    // The borrower puts up 10 COL, worth $1,000, and borrows $700 of USDC
+    const borrowerCOLAccount = await createAccount(
+      // @ts-ignores
+      banksClient,
+      signer,
+      mintCOL,
+      borrower.publicKey
+    );
+    await mintTo(
+      // @ts-ignores
+      banksClient,
+      signer,
+      mintCOL,
+      borrowerCOLAccount,
+      signer,
+      10 * 10 ** 6
+    );
+
+    await program.methods
+      .deposit(new BN(10 * 10 ** 6), false)
+      .accountsPartial({
+        signer: borrower.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: mintCOL,
+        userTokenAccount: borrowerCOLAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .preInstructions([await refreshBank(colBank)])
+      .signers([borrower])
+      .rpc({ commitment: "confirmed" });
+
+    await program.methods
+      .depositCollateral(new BN(10 * 10 ** 6))
+      .accountsPartial({
+        signer: borrower.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        bank: colBank,
+        receiptMint: colReceiptMint,
+        userReceiptTokenAccount: getAssociatedTokenAddressSync(
+          colReceiptMint,
+          borrower.publicKey
+        ),
+        userAccount: borrowerAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([borrower])
+      .rpc({ commitment: "confirmed" });
+
+    await program.methods
+      .borrow(new BN(700 * 10 ** 2), false)
+      .accountsPartial({
+        signer: borrower.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        mint: mintUSDC,
+        userAccount: borrowerAccount,
+        hostFeeReceiver: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(health())
+      .preInstructions([
+        await refreshBank(colBank),
+        await refreshBank(usdcBank),
+      ])
+      .signers([borrower])
+      .rpc({ commitment: "confirmed" });
+
+    // The keeper holds the USDC it repays with
+    const keeperUSDCAccount = await createAccount(
+      // @ts-ignores
+      banksClient,
+      signer,
+      mintUSDC,
+      keeper.publicKey
+    );
+    await mintTo(
+      // @ts-ignores
+      banksClient,
+      signer,
+      mintUSDC,
+      keeperUSDCAccount,
+      signer,
+      1_000 * 10 ** 2
+    );
+    const keeperCOLAccount = getAssociatedTokenAddressSync(
+      mintCOL,
+      keeper.publicKey
+    );
+
+    // A year of interest lifts the USDC borrow index above one
+    await bankrunContextWrapper.moveTimeForward(365 * 24 * 60 * 60);
+    await setPythPriceUpdate(
+      context,
+      usdcUsdPriceFeedAccount,
+      USDC_PRICE_FEED_ID,
+      { price: 10 ** 8, confidence: 10 ** 4 }
+    );
+    await program.methods
+      .refreshBank()
+      .accounts({ bank: usdcBank })
+      .postInstructions([await refreshBank(colBank)])
+      .rpc({ commitment: "confirmed" });
+
+    // At $80 the collateral only supports $680 of debt
+    await program.methods
+      .setTestOraclePrice(new BN(80 * 10 ** 8), -8, new BN(0), null)
+      .accounts({
+        owner: signer.publicKey,
+        lendingMarket: lendingMarket.publicKey,
+        testOracle: colTestOracle,
+      })
+      .rpc({ commitment: "confirmed" });
+
+    const liquidate = async (repayAmount: BN, minCollateralOut: BN) =>
+      program.methods
+        .liquidate(repayAmount, minCollateralOut)
+        .accountsPartial({
+          liquidator: keeper.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          collateralMint: mintCOL,
+          borrowedMint: mintUSDC,
+          userAccount: borrowerAccount,
+          collateralTokenProgram: TOKEN_PROGRAM_ID,
+          borrowedTokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts(health())
+        .preInstructions([
+          await refreshBank(colBank),
+          await refreshBank(usdcBank),
+        ])
+        .signers([keeper])
+        .rpc({ commitment: "confirmed" });
+
+    const tokenBalance = async (account: PublicKey) => {
+      const info = await banksClient.getAccount(account);
+      return info === null ? 0 : Number(AccountLayout.decode(info.data).amount);
+    };
+    const shares = (
+      positions: { bank: PublicKey; shares: BN }[],
+      bank: PublicKey
+    ) =>
+      positions.find((position) => position.bank.equals(bank))?.shares ??
+      new BN(0);
+
+    const userBefore = await program.account.user.fetch(borrowerAccount);
+    const usdcBankBefore = await program.account.bank.fetch(usdcBank);
+    const colBankBefore = await program.account.bank.fetch(colBank);
+    const wad = new BN(10).pow(new BN(18));
+    const borrowIndex = new BN(usdcBankBefore.cumulativeBorrowIndex.toString());
+    assert.ok(borrowIndex.gt(wad));
+    const debtBefore = shares(userBefore.borrows, usdcBank)
+      .mul(borrowIndex)
+      .div(wad)
+      .toNumber();
+    const keeperUSDCBefore = await tokenBalance(keeperUSDCAccount);
+
+    // One base unit no longer covers a debt share, so it seizes nothing
+    await assert.rejects(liquidate(new BN(1), new BN(1)), /ZeroShares/);
+
+    // Asking for more collateral than the repayment is worth is rejected
+    await assert.rejects(
+      liquidate(new BN(debtBefore), new BN(10 * 10 ** 6)),
+      /LiquidationSlippage/
+    );
+
+    const liquidateTx = await liquidate(new BN(debtBefore), new BN(1));
+    console.log("Liquidate Borrower", liquidateTx);
+
+    // Only half the debt could be repaid, the close factor
+    const repaid = keeperUSDCBefore - (await tokenBalance(keeperUSDCAccount));
+    assert.ok(repaid > 0 && repaid <= debtBefore / 2, `repaid ${repaid}`);
+
+    const seized = await tokenBalance(keeperCOLAccount);
+    assert.ok(seized > 0);
+
+    const userAfter = await program.account.user.fetch(borrowerAccount);
+    const usdcBankAfter = await program.account.bank.fetch(usdcBank);
+    const colBankAfter = await program.account.bank.fetch(colBank);
+
+    assert.ok(
+      shares(userAfter.borrows, usdcBank).lt(
+        shares(userBefore.borrows, usdcBank)
+      )
+    );
+    assert.equal(
+      shares(userBefore.deposits, colBank)
+        .sub(shares(userAfter.deposits, colBank))
+        .toNumber(),
+      seized
+    );
+    assert.ok(usdcBankAfter.totalBorrowed.lt(usdcBankBefore.totalBorrowed));
+    assert.equal(
+      colBankBefore.totalDeposits.sub(colBankAfter.totalDeposits).toNumber(),
+      seized
+    );
+
+    // The liquidation leaves the borrower healthy again
+    assert.ok(!userAfter.healthFactor.eq(userBefore.healthFactor));
+    assert.ok(userAfter.healthFactor.gte(new BN(10_000)));
+  });
+
+  it("Test Collect Fees", async () => {
+    // The liquidated USDC borrow paid an origination fee and a year of interest
+    const signerUSDCAccount = getAssociatedTokenAddressSync(
+      mintUSDC,
+      signer.publicKey
+    );
+    const collectFees = (feeReceiver: PublicKey) =>
+      program.methods
+        .collectFees()
+        .accounts({
+          feeAdmin: signer.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          mint: mintUSDC,
+          feeReceiver,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc({ commitment: "confirmed" });
+    const balance = async (account: PublicKey) =>
+      Number(
+        AccountLayout.decode((await banksClient.getAccount(account)).data)
+          .amount
+      );
+
+    // Only the bank's fee receiver can be paid
+    const otherUSDCAccount = await createAccount(
+      // @ts-ignores
+      banksClient,
+      signer,
+      mintUSDC,
+      signer.publicKey,
+      Keypair.generate()
+    );
+    await assert.rejects(collectFees(otherUSDCAccount), /InvalidFeeReceiver/);
+
+    const wad = new BN(10).pow(new BN(18));
+    const bankBefore = await program.account.bank.fetch(usdcBank);
+    const fees = new BN(bankBefore.accumulatedProtocolFees.toString());
+    // Collecting never takes the treasury below deposits net of borrows
+    const available =
+      (await balance(usdcBankAccount)) -
+      bankBefore.totalDeposits.sub(bankBefore.totalBorrowed).toNumber();
+    const collectable = Math.min(fees.div(wad).toNumber(), available);
+    assert.ok(collectable > 0);
+
+    const receiverBefore = await balance(signerUSDCAccount);
+    const collectTx = await collectFees(signerUSDCAccount);
+    console.log("Collect USDC Fees", collectTx);
+
+    assert.equal(
+      (await balance(signerUSDCAccount)) - receiverBefore,
+      collectable
+    );
+    const bankAfter = await program.account.bank.fetch(usdcBank);
+    assert.ok(
+      new BN(bankAfter.accumulatedProtocolFees.toString()).eq(
+        fees.sub(new BN(collectable).mul(wad))
+      )
+    );
+
+    // Refreshing first keeps this transaction distinct from the collection above
+    await assert.rejects(
+      program.methods
+        .collectFees()
+        .accounts({
+          feeAdmin: signer.publicKey,
+          lendingMarket: lendingMarket.publicKey,
+          mint: mintUSDC,
+          feeReceiver: signerUSDCAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .preInstructions([await refreshBank(usdcBank)])
+        .rpc({ commitment: "confirmed" }),
+      /NoFeesToCollect/
+    );
+  });
 });